@@ -0,0 +1,53 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+
+
+/// Embedded index, produced with `index rebuild` and `gzip`.
+const INDEX_DB_GZ: &'static str = "data/index.db.gz";
+
+/// Set to fail the build without an embedded index, e.g. for releases.
+const REQUIRE_INDEX_VAR: &'static str = "CPPMAN_RS_REQUIRE_EMBEDDED_INDEX";
+
+
+/// FNV-1a hash, good enough to tell two index snapshots apart.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let dest = Path::new(&out_dir).join("index.db.gz");
+
+    println!("cargo:rerun-if-changed={}", INDEX_DB_GZ);
+    println!("cargo:rerun-if-env-changed={}", REQUIRE_INDEX_VAR);
+
+    let mut data = Vec::new();
+    let missing = match File::open(INDEX_DB_GZ) {
+        Ok(mut file) => {
+            file.read_to_end(&mut data).unwrap();
+            if data.is_empty() { Some("it is empty".to_owned()) } else { None }
+        },
+        Err(e) => Some(e.to_string()),
+    };
+
+    // Checkouts don't come with an index, builds work without one
+    if let Some(reason) = missing {
+        if env::var_os(REQUIRE_INDEX_VAR).is_some() {
+            panic!("cannot read the index to embed, {}: {}\n\
+                    Generate it as described in README.md, or unset {} \
+                    to build without it.", INDEX_DB_GZ, reason, REQUIRE_INDEX_VAR);
+        }
+        println!("cargo:warning=building without an embedded index, {}: {}; \
+                  `index rebuild` is needed before pages can be looked up",
+                 INDEX_DB_GZ, reason);
+    }
+
+    // An empty file means there is no embedded index
+    let version = if data.is_empty() { "none".to_owned() } else { format!("{:016x}", fnv1a(&data)) };
+    println!("cargo:rustc-env=CPPMAN_INDEX_VERSION={}", version);
+
+    fs::create_dir_all(&out_dir).unwrap();
+    File::create(&dest).unwrap().write_all(&data).unwrap();
+}
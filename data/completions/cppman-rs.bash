@@ -0,0 +1,60 @@
+# bash completion for cppman-rs
+#
+# Generated by `cppman-rs completions bash`.
+
+_cppman_rs_pages() {
+    cppman-rs complete-page -- "$1" 2>/dev/null
+}
+
+_cppman_rs() {
+    local cur subcommand
+    # Page names contain colons, which bash treats as word breaks
+    if declare -F _get_comp_words_by_ref >/dev/null; then
+        _get_comp_words_by_ref -n : cur
+    else
+        cur="${COMP_WORDS[COMP_CWORD]}"
+    fi
+    subcommand="${COMP_WORDS[1]}"
+
+    COMPREPLY=()
+    if [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "view find lookup header which-header members \
+                                 example cache index config browse serve \
+                                 vim-plugin completions mandb" -- "$cur"))
+        local IFS=$'\n'
+        COMPREPLY+=($(_cppman_rs_pages "$cur"))
+    else
+        case "$subcommand" in
+            view|lookup)
+                local IFS=$'\n'
+                COMPREPLY=($(_cppman_rs_pages "$cur"))
+                ;;
+            header)      ;;
+            members|example)
+                if [[ $cur == -* ]]; then
+                    local options="--hierarchy"
+                    [[ $subcommand == example ]] && options="--out --run"
+                    COMPREPLY=($(compgen -W "$options" -- "$cur"))
+                else
+                    local IFS=$'\n'
+                    COMPREPLY=($(_cppman_rs_pages "$cur"))
+                fi
+                ;;
+            cache)       COMPREPLY=($(compgen -W "all clear reformat" -- "$cur")) ;;
+            index)       COMPREPLY=($(compgen -W "rebuild" -- "$cur")) ;;
+            config)      COMPREPLY=($(compgen -W "get set list reset" -- "$cur")) ;;
+            vim-plugin)  COMPREPLY=($(compgen -W "install print" -- "$cur")) ;;
+            completions) COMPREPLY=($(compgen -W "bash zsh fish" -- "$cur")) ;;
+            *)
+                local IFS=$'\n'
+                COMPREPLY=($(_cppman_rs_pages "$cur"))
+                ;;
+        esac
+    fi
+
+    if declare -F __ltrim_colon_completions >/dev/null; then
+        __ltrim_colon_completions "$cur"
+    fi
+}
+
+complete -F _cppman_rs cppman-rs
@@ -0,0 +1,22 @@
+# fish completion for cppman-rs
+#
+# Generated by `cppman-rs completions fish`.
+
+function __cppman_rs_pages
+    cppman-rs complete-page -- (commandline -ct) 2>/dev/null
+end
+
+set -l subcommands view find lookup header which-header members example cache index config browse serve vim-plugin completions mandb
+
+complete -c cppman-rs -f
+complete -c cppman-rs -n "not __fish_seen_subcommand_from $subcommands" -a "$subcommands"
+complete -c cppman-rs -n "not __fish_seen_subcommand_from $subcommands" -a "(__cppman_rs_pages)"
+complete -c cppman-rs -n "__fish_seen_subcommand_from view lookup which-header members example" -a "(__cppman_rs_pages)"
+complete -c cppman-rs -n "__fish_seen_subcommand_from members" -l hierarchy -d "print the base classes first"
+complete -c cppman-rs -n "__fish_seen_subcommand_from example" -l out -r -a "(__fish_complete_directories)" -d "save the example under a directory"
+complete -c cppman-rs -n "__fish_seen_subcommand_from example" -l run -d "compile and run the example"
+complete -c cppman-rs -n "__fish_seen_subcommand_from cache" -a "all clear reformat"
+complete -c cppman-rs -n "__fish_seen_subcommand_from index" -a "rebuild"
+complete -c cppman-rs -n "__fish_seen_subcommand_from config" -a "get set list reset"
+complete -c cppman-rs -n "__fish_seen_subcommand_from vim-plugin" -a "install print"
+complete -c cppman-rs -n "__fish_seen_subcommand_from completions" -a "bash zsh fish"
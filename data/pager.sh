@@ -0,0 +1,40 @@
+#!/bin/sh
+#
+# cppman-rs pager: render a page and show it.
+#
+# Usage: pager.sh PAGER PAGE COLUMNS VIM_CONFIG PAGE_NAME
+#
+# PAGER is `vim`, `less` or `system`, PAGE the groff page, gzip-compressed if
+# its name ends in `.gz`. The page is rendered for the groff output device in
+# CPPMAN_RS_GROFF_DEVICE, `ascii` if unset.
+#
+# Generated by cppman-rs.
+
+pager=$1
+page=$2
+columns=$3
+vim_config=$4
+page_name=$5
+device=${CPPMAN_RS_GROFF_DEVICE:-ascii}
+
+# Render the page with the options given, after the common ones
+render() {
+    case "$page" in
+        *.gz) gzip -dc "$page" ;;
+        *)    cat "$page" ;;
+    esac | groff -t -m man -T"$device" -rLL="${columns}n" -rLT="${columns}n" "$@" 2>/dev/null
+}
+
+case "$pager" in
+    vim)
+        # Vim gets plain text, without bold and underline
+        render -P-cbou | CPPMAN_RS_PAGE="$page_name" vim -R -S "$vim_config" \
+            -c 'let b:cppman_page = $CPPMAN_RS_PAGE | setfiletype cppman | normal! gg' -
+        ;;
+    less)
+        render | less -R
+        ;;
+    *)
+        render | ${PAGER:-less -R}
+        ;;
+esac
@@ -0,0 +1,62 @@
+" cppman-rs: page loading and navigation
+"
+" Generated by `cppman-rs vim-plugin install`.
+
+if !exists("g:cppman_rs_command")
+  let g:cppman_rs_command = "cppman-rs"
+endif
+
+" Pages visited before the current one, as [page, cursor position] pairs
+let s:stack = []
+
+function! s:Load(page) abort
+  let l:width = winwidth(0) > 20 ? winwidth(0) - 2 : 78
+  let l:text = system(g:cppman_rs_command . " view --force-columns " . l:width
+                    \ . " " . shellescape(a:page))
+  if v:shell_error
+    echohl ErrorMsg
+    echo "cppman-rs: no manual entry for " . a:page
+    echohl None
+    return 0
+  endif
+
+  if &filetype !=# "cppman"
+    new
+  endif
+
+  setlocal modifiable noreadonly
+  silent %delete _
+  " Drop bold and underline overstrikes produced by groff
+  call setline(1, split(substitute(l:text, '.\b', '', 'g'), "\n"))
+  setlocal nomodifiable readonly nomodified
+  let b:cppman_page = a:page
+  setfiletype cppman
+  normal! gg
+  return 1
+endfunction
+
+" Open a page, remembering the current one so that it can be returned to.
+function! cppman#Open(page) abort
+  let l:previous = exists("b:cppman_page") ? [b:cppman_page, getpos(".")] : []
+  if s:Load(a:page) && !empty(l:previous)
+    call add(s:stack, l:previous)
+  endif
+endfunction
+
+" Follow a see-also link such as `vector::insert(3)` under the cursor.
+function! cppman#FollowLink() abort
+  let l:link = matchstr(expand("<cWORD>"), '[^"(]\+\ze(3[a-z]*)')
+  call cppman#Open(empty(l:link) ? expand("<cword>") : l:link)
+endfunction
+
+" Return to the previous page.
+function! cppman#Back() abort
+  if empty(s:stack)
+    return
+  endif
+
+  let [l:page, l:pos] = remove(s:stack, -1)
+  if s:Load(l:page)
+    call setpos(".", l:pos)
+  endif
+endfunction
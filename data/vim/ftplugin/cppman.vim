@@ -0,0 +1,19 @@
+" cppman-rs: settings for rendered pages
+"
+" Generated by `cppman-rs vim-plugin install`.
+
+if exists("b:did_ftplugin")
+  finish
+endif
+let b:did_ftplugin = 1
+
+setlocal buftype=nofile bufhidden=hide noswapfile nonumber nolist
+setlocal nomodifiable readonly
+" Keep operators and qualified names together as a single keyword
+setlocal iskeyword+=:,=,~,[,],*,!,<,>
+
+nnoremap <buffer> <silent> K     :call cppman#Open(expand("<cword>"))<CR>
+nnoremap <buffer> <silent> <C-]> :call cppman#Open(expand("<cword>"))<CR>
+nnoremap <buffer> <silent> <CR>  :call cppman#FollowLink()<CR>
+nnoremap <buffer> <silent> <C-T> :call cppman#Back()<CR>
+nnoremap <buffer> <silent> q     :quit<CR>
@@ -0,0 +1,20 @@
+" cppman-rs: C++ manual pages in Vim
+"
+" Generated by `cppman-rs vim-plugin install`.
+
+if exists("g:loaded_cppman_rs")
+  finish
+endif
+let g:loaded_cppman_rs = 1
+
+if !exists("g:cppman_rs_command")
+  let g:cppman_rs_command = "cppman-rs"
+endif
+
+command! -nargs=+ Cppman call cppman#Open(<q-args>)
+
+augroup cppman_rs
+  autocmd!
+  " Make `K` in C++ buffers look the keyword up with cppman-rs
+  autocmd FileType cpp setlocal keywordprg=:Cppman
+augroup END
@@ -0,0 +1,24 @@
+" cppman-rs: syntax highlighting for rendered pages
+"
+" Generated by `cppman-rs vim-plugin install`.
+
+if exists("b:current_syntax")
+  finish
+endif
+
+syntax include @cppmanCpp syntax/cpp.vim
+unlet! b:current_syntax
+
+syntax match  cppmanTitle      "^\S.\+(3[a-z]*)\s.*$"
+syntax match  cppmanSection    "^[A-Z][A-Z0-9 ()-]*$"
+syntax match  cppmanSubSection "^   \S.*$"
+syntax match  cppmanLink       "[A-Za-z_~][A-Za-z0-9_:<>=!~*\[\]+-]*(3[a-z]*)"
+syntax region cppmanCode       start="^SYNOPSIS$"ms=e+1 end="^\ze[A-Z]" contains=@cppmanCpp keepend
+syntax region cppmanCode       start="^EXAMPLE$"ms=e+1 end="^\ze[A-Z]" contains=@cppmanCpp keepend
+
+highlight default link cppmanTitle      Title
+highlight default link cppmanSection    Statement
+highlight default link cppmanSubSection PreProc
+highlight default link cppmanLink       Underlined
+
+let b:current_syntax = "cppman"
@@ -0,0 +1,257 @@
+use clap::{App, AppSettings, Arg, SubCommand};
+
+
+/// Exit codes shown at the end of `--help`.
+const EXIT_CODES: &'static str = "\
+EXIT CODES:
+    0    success
+    1    generic failure
+    2    invalid command line
+    3    no matching page or index entry
+    4    network error while downloading a page
+    5    no index database, run `cppman-rs index rebuild`
+    6    invalid configuration";
+
+
+fn manpage_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("manpage")
+        .multiple(true)
+        .help("Requested manpages")
+}
+
+fn force_update_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("force-update")
+        .help("Force cppman-rs to update existing cache when caching all \
+               pages or browsing man pages that were already cached.")
+        .short("o")
+        .long("force-update")
+}
+
+fn force_columns_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("force-columns")
+        .help("Force terminal columns.")
+        .long("force-columns")
+        .takes_value(true)
+}
+
+fn section_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("section")
+        .help("Only show these sections of the page, e.g. SYNOPSIS, \
+               PARAMETERS, 'RETURN VALUE', COMPLEXITY, EXCEPTIONS or EXAMPLE.")
+        .long("section")
+        .value_name("NAME,...")
+        .takes_value(true)
+        .use_delimiter(true)
+}
+
+fn brief_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("brief")
+        .help("Only show the NAME and SYNOPSIS sections of the page.")
+        .long("brief")
+        .conflicts_with("section")
+}
+
+fn format_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("format")
+        .help("Output format. 'ndjson' writes one JSON object per line as \
+               results become available.")
+        .long("format")
+        .takes_value(true)
+        .possible_values(&["text", "json", "ndjson"])
+        .default_value("text")
+}
+
+
+/// Build the command line interface.
+///
+/// A bare `cppman-rs PAGE...` is a shorthand for `cppman-rs view PAGE...`.
+pub fn build_cli() -> App<'static, 'static> {
+    App::new("cppman-rs")
+        .version(crate_version!())
+        .about("Rust port of cppman, originally written in Python")
+        .after_help(EXIT_CODES)
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(Arg::with_name("config-dir")
+                 .help("Directory holding the configuration file. \
+                        [default: $CPPMAN_CONFIG_DIR or $XDG_CONFIG_HOME/cppman-rs]")
+                 .long("config-dir")
+                 .takes_value(true)
+                 .global(true))
+        .arg(Arg::with_name("data-dir")
+                 .help("Directory holding the index database and resources. \
+                        [default: $CPPMAN_DATA_DIR or $XDG_DATA_HOME/cppman-rs]")
+                 .long("data-dir")
+                 .takes_value(true)
+                 .global(true))
+        .arg(Arg::with_name("cache-dir")
+                 .help("Directory holding downloaded pages. \
+                        [default: $CPPMAN_CACHE_DIR or $XDG_CACHE_HOME/cppman-rs]")
+                 .long("cache-dir")
+                 .takes_value(true)
+                 .global(true))
+        .arg(Arg::with_name("man-dir")
+                 .help("Directory holding rendered man pages. \
+                        [default: $CPPMAN_MAN_DIR or $XDG_DATA_HOME/man]")
+                 .long("man-dir")
+                 .takes_value(true)
+                 .global(true))
+        .arg(Arg::with_name("record")
+                 .help("Store every downloaded page under this directory, \
+                        for replaying later with --replay.")
+                 .long("record")
+                 .value_name("DIR")
+                 .takes_value(true)
+                 .conflicts_with("replay")
+                 .global(true))
+        .arg(Arg::with_name("replay")
+                 .help("Answer downloads from pages recorded with --record \
+                        instead of the network. Pages that weren't recorded \
+                        are treated as missing.")
+                 .long("replay")
+                 .value_name("DIR")
+                 .takes_value(true)
+                 .global(true))
+        .arg(force_update_arg())
+        .arg(force_columns_arg())
+        .arg(section_arg())
+        .arg(brief_arg())
+        .arg(manpage_arg())
+        .subcommand(SubCommand::with_name("view")
+                        .about("View man pages")
+                        .arg(force_update_arg())
+                        .arg(force_columns_arg())
+                        .arg(section_arg())
+                        .arg(brief_arg())
+                        .arg(manpage_arg().required(true)))
+        .subcommand(SubCommand::with_name("find")
+                        .about("Find man pages in the index")
+                        .arg(format_arg())
+                        .arg(Arg::with_name("pattern")
+                                 .help("Part of the page name")
+                                 .required(true)))
+        .subcommand(SubCommand::with_name("lookup")
+                        .about("Print the index entry each page name resolves to, \
+                                with its cache status")
+                        .arg(format_arg())
+                        .arg(manpage_arg().required(true)))
+        .subcommand(SubCommand::with_name("header")
+                        .about("List the indexed symbols declared in a header, \
+                                grouped by kind")
+                        .arg(Arg::with_name("header")
+                                 .help("Header, e.g. '<algorithm>'")
+                                 .required(true)))
+        .subcommand(SubCommand::with_name("which-header")
+                        .about("Print the headers declaring a symbol")
+                        .arg(Arg::with_name("name")
+                                 .help("Symbol, e.g. std::move")
+                                 .required(true)))
+        .subcommand(SubCommand::with_name("members")
+                        .about("Print the member types and functions of a class, \
+                                including inherited ones with the class they come from")
+                        .arg(Arg::with_name("hierarchy")
+                                 .help("Print the base classes first.")
+                                 .long("hierarchy"))
+                        .arg(Arg::with_name("class")
+                                 .required(true)))
+        .subcommand(SubCommand::with_name("example")
+                        .about("Print, save or run the example on a page")
+                        .arg(Arg::with_name("out")
+                                 .help("Write the example to DIR as a .cpp file \
+                                        instead of printing it.")
+                                 .long("out")
+                                 .value_name("DIR")
+                                 .takes_value(true))
+                        .arg(Arg::with_name("run")
+                                 .help("Compile the example with $CXX for StdVersion, \
+                                        run it and compare its output to the \
+                                        documented one.")
+                                 .long("run"))
+                        .arg(Arg::with_name("page")
+                                 .required(true)))
+        .subcommand(SubCommand::with_name("cache")
+                        .about("Manage cached man pages")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(SubCommand::with_name("all")
+                                        .about("Cache all available man pages to \
+                                                enable offline browsing")
+                                        .arg(force_update_arg()))
+                        .subcommand(SubCommand::with_name("clear")
+                                        .about("Clear all cached files"))
+                        .subcommand(SubCommand::with_name("reformat")
+                                        .about("Regenerate all cached man pages from \
+                                                the stored HTML with the current \
+                                                formatter")))
+        .subcommand(SubCommand::with_name("index")
+                        .about("Manage the index database")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(SubCommand::with_name("rebuild")
+                                        .about("Rebuild the index database from \
+                                                cplusplus.com and cppreference.com, \
+                                                resuming an interrupted rebuild")
+                                        .arg(Arg::with_name("restart")
+                                                 .long("restart")
+                                                 .help("Discard an interrupted rebuild \
+                                                        and start over"))))
+        .subcommand(SubCommand::with_name("config")
+                        .about("Manage settings")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(SubCommand::with_name("get")
+                                        .about("Print the value of a setting")
+                                        .arg(Arg::with_name("key").required(true)))
+                        .subcommand(SubCommand::with_name("set")
+                                        .about("Change the value of a setting")
+                                        .arg(Arg::with_name("key").required(true))
+                                        .arg(Arg::with_name("value").required(true)))
+                        .subcommand(SubCommand::with_name("list")
+                                        .about("Print all settings with their values"))
+                        .subcommand(SubCommand::with_name("reset")
+                                        .about("Reset a setting, or all of them, to \
+                                                the default")
+                                        .arg(Arg::with_name("key"))))
+        .subcommand(SubCommand::with_name("browse")
+                        .about("Browse the index interactively: search, preview \
+                                pages and follow see-also links"))
+        .subcommand(SubCommand::with_name("serve")
+                        .about("Answer JSON-RPC documentation requests on stdin, \
+                                for editor hover integration"))
+        .subcommand(SubCommand::with_name("vim-plugin")
+                        .about("Manage the Vim plugin: syntax highlighting for pages, \
+                                `K' lookup and see-also links")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(SubCommand::with_name("install")
+                                        .about("Install the plugin as a Vim 8 or Neovim \
+                                                package")
+                                        .arg(Arg::with_name("editor")
+                                                 .help("Editor to install for.")
+                                                 .long("editor")
+                                                 .takes_value(true)
+                                                 .possible_values(&["vim", "nvim"])
+                                                 .default_value("vim"))
+                                        .arg(Arg::with_name("dir")
+                                                 .help("Install under this directory \
+                                                        instead of the editor's package \
+                                                        directory.")
+                                                 .long("dir")
+                                                 .takes_value(true)))
+                        .subcommand(SubCommand::with_name("print")
+                                        .about("Print a script loading the plugin, written to \
+                                                the data directory")))
+        .subcommand(SubCommand::with_name("completions")
+                        .about("Print a shell completion script")
+                        .arg(Arg::with_name("shell")
+                                 .required(true)
+                                 .possible_values(&["bash", "zsh", "fish"])))
+        .subcommand(SubCommand::with_name("complete-page")
+                        .about("List page names starting with a prefix, used by \
+                                the completion scripts")
+                        .setting(AppSettings::Hidden)
+                        .arg(Arg::with_name("prefix")
+                                 .default_value("")))
+        .subcommand(SubCommand::with_name("mandb")
+                        .about("Add the man page cache to mandb so that C++ man \
+                                pages can be viewed with `man', remove it again or \
+                                show whether it is registered")
+                        .arg(Arg::with_name("state")
+                                 .possible_values(&["on", "off", "status"])
+                                 .default_value("on")))
+}
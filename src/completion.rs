@@ -0,0 +1,28 @@
+use ::errors;
+
+
+#[derive(Copy, Clone)] pub enum Shell { Bash, Zsh, Fish }
+
+
+impl Shell {
+    pub fn try_from(s: &str) -> errors::Result<Shell> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh"  => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _      => Err(errors::ErrorKind::Usage(format!("unknown shell '{}'", s)).into()),
+        }
+    }
+}
+
+
+/// Completion script for `shell`.
+///
+/// Page names are completed by calling `cppman-rs complete-page PREFIX`.
+pub fn script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => include_str!("../data/completions/cppman-rs.bash"),
+        Shell::Zsh  => include_str!("../data/completions/cppman-rs.zsh"),
+        Shell::Fish => include_str!("../data/completions/cppman-rs.fish"),
+    }
+}
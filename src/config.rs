@@ -1,10 +1,13 @@
 use std::cell::RefCell;
 use std::fmt::{self, Display, Formatter};
 use std::fs::{self, File};
-use std::io;
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use ini::Ini;
+use regex::Regex;
+use url::Url;
 
 use ::errors;
 
@@ -12,6 +15,10 @@ use ::errors;
 #[derive(Copy, Clone)] pub enum Pager { Vim, Less, System }
 #[derive(Copy, Clone)] struct UpdateManPath(bool);
 #[derive(Copy, Clone)] pub enum Source { CPlusPlus, CppReference }
+#[derive(Copy, Clone, PartialEq)] pub enum Colour { Auto, Always, Never }
+#[derive(Copy, Clone, PartialEq, Debug)] pub enum Charset { Auto, Ascii, Utf8 }
+#[derive(Copy, Clone, PartialEq, PartialOrd)] pub enum StdVersion { Cpp98, Cpp11, Cpp14, Cpp17 }
+#[derive(Copy, Clone, PartialEq)] pub enum ManSection { Man3, Man3Cpp }
 
 
 impl Display for Pager {
@@ -32,6 +39,30 @@ impl Display for Source {
     }
 }
 
+impl Display for Colour {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&'static str>::into(*self))
+    }
+}
+
+impl Display for Charset {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&'static str>::into(*self))
+    }
+}
+
+impl Display for StdVersion {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&'static str>::into(*self))
+    }
+}
+
+impl Display for ManSection {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&'static str>::into(*self))
+    }
+}
+
 
 impl Pager {
     pub fn try_from(s: &str) -> errors::Result<Pager> {
@@ -79,6 +110,77 @@ impl<'a> From<&'a str> for Source {
 }
 
 
+impl Colour {
+    pub fn try_from(s: &str) -> errors::Result<Colour> {
+        match s {
+            "auto"   => Ok(Colour::Auto),
+            "always" => Ok(Colour::Always),
+            "never"  => Ok(Colour::Never),
+            _        => Err(errors::ErrorKind::ParseColour(s.to_owned()).into()),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Colour {
+    fn from(s: &str) -> Colour {
+        Colour::try_from(s).unwrap_or_default()
+    }
+}
+
+
+impl Charset {
+    pub fn try_from(s: &str) -> errors::Result<Charset> {
+        match s {
+            "auto"  => Ok(Charset::Auto),
+            "ascii" => Ok(Charset::Ascii),
+            "utf-8" => Ok(Charset::Utf8),
+            _       => Err(errors::ErrorKind::ParseCharset(s.to_owned()).into()),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Charset {
+    fn from(s: &str) -> Charset {
+        Charset::try_from(s).unwrap_or_default()
+    }
+}
+
+
+impl StdVersion {
+    pub fn try_from(s: &str) -> errors::Result<StdVersion> {
+        match s {
+            "c++98" => Ok(StdVersion::Cpp98),
+            "c++11" => Ok(StdVersion::Cpp11),
+            "c++14" => Ok(StdVersion::Cpp14),
+            "c++17" => Ok(StdVersion::Cpp17),
+            _       => Err(errors::ErrorKind::ParseStdVersion(s.to_owned()).into()),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for StdVersion {
+    fn from(s: &str) -> StdVersion {
+        StdVersion::try_from(s).unwrap_or_default()
+    }
+}
+
+impl ManSection {
+    pub fn try_from(s: &str) -> errors::Result<ManSection> {
+        match s {
+            "3"    => Ok(ManSection::Man3),
+            "3cpp" => Ok(ManSection::Man3Cpp),
+            _      => Err(errors::ErrorKind::ParseManSection(s.to_owned()).into()),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for ManSection {
+    fn from(s: &str) -> ManSection {
+        ManSection::try_from(s).unwrap_or_default()
+    }
+}
+
+
 impl Into<&'static str> for Pager {
     fn into(self) -> &'static str {
         match self {
@@ -98,6 +200,46 @@ impl Into<&'static str> for Source {
     }
 }
 
+impl Into<&'static str> for Colour {
+    fn into(self) -> &'static str {
+        match self {
+            Colour::Auto   => "auto",
+            Colour::Always => "always",
+            Colour::Never  => "never",
+        }
+    }
+}
+
+impl Into<&'static str> for Charset {
+    fn into(self) -> &'static str {
+        match self {
+            Charset::Auto  => "auto",
+            Charset::Ascii => "ascii",
+            Charset::Utf8  => "utf-8",
+        }
+    }
+}
+
+impl Into<&'static str> for StdVersion {
+    fn into(self) -> &'static str {
+        match self {
+            StdVersion::Cpp98 => "c++98",
+            StdVersion::Cpp11 => "c++11",
+            StdVersion::Cpp14 => "c++14",
+            StdVersion::Cpp17 => "c++17",
+        }
+    }
+}
+
+impl Into<&'static str> for ManSection {
+    fn into(self) -> &'static str {
+        match self {
+            ManSection::Man3    => "3",
+            ManSection::Man3Cpp => "3cpp",
+        }
+    }
+}
+
 
 impl Default for Pager {
     fn default() -> Pager {
@@ -117,6 +259,182 @@ impl Default for Source {
     }
 }
 
+impl Default for Colour {
+    fn default() -> Colour {
+        Colour::Auto
+    }
+}
+
+impl Default for Charset {
+    fn default() -> Charset {
+        Charset::Auto
+    }
+}
+
+impl Default for StdVersion {
+    fn default() -> StdVersion {
+        StdVersion::Cpp14
+    }
+}
+
+impl Default for ManSection {
+    fn default() -> ManSection {
+        ManSection::Man3
+    }
+}
+
+
+/// A single entry of the configuration schema.
+pub struct Key {
+    pub name: &'static str,
+    pub default: &'static str,
+    pub help: &'static str,
+    validate: fn(&str) -> Result<(), String>,
+}
+
+/// All keys accepted in the `[Settings]` section.
+///
+/// An empty value for an optional key means "use the built-in behaviour".
+pub static KEYS: &'static [Key] = &[
+    Key { name: "Source", default: "cplusplus.com",
+          help: "documentation source, only 'cplusplus.com' for now",
+          validate: validate_source },
+    Key { name: "Pager", default: "vim",
+          help: "pager used to display pages, 'vim', 'less' or 'system'",
+          validate: validate_pager },
+    Key { name: "UpdateManPath", default: "false",
+          help: "whether the cache is registered with mandb, 'true' or 'false'",
+          validate: validate_bool },
+    Key { name: "CacheDir", default: "",
+          help: "directory for downloaded HTML, empty for the default",
+          validate: validate_path },
+    Key { name: "IndexPath", default: "",
+          help: "path to the index database, empty for the default",
+          validate: validate_path },
+    Key { name: "Concurrency", default: "4",
+          help: "number of pages fetched in parallel, at least 1",
+          validate: validate_positive },
+    Key { name: "ConnectTimeout", default: "10",
+          help: "connection timeout in seconds, 0 to disable; the larger of both timeouts bounds whole requests",
+          validate: validate_number },
+    Key { name: "ReadTimeout", default: "30",
+          help: "read timeout in seconds, 0 to disable; the larger of both timeouts bounds whole requests",
+          validate: validate_number },
+    Key { name: "Proxy", default: "",
+          help: "HTTP proxy URL, empty to use the environment",
+          validate: validate_url },
+    Key { name: "CaBundle", default: "",
+          help: "extra CA certificates trusted for HTTPS, PEM or DER",
+          validate: validate_path },
+    Key { name: "Retries", default: "4",
+          help: "times a failed download is retried with exponential backoff",
+          validate: validate_number },
+    Key { name: "Colour", default: "auto",
+          help: "colourise output, 'auto', 'always' or 'never'",
+          validate: validate_colour },
+    Key { name: "Charset", default: "auto",
+          help: "characters of rendered pages, 'ascii', 'utf-8' or 'auto' to follow the locale",
+          validate: validate_charset },
+    Key { name: "StdVersion", default: "c++14",
+          help: "C++ standard version, 'c++98', 'c++11', 'c++14' or 'c++17'",
+          validate: validate_std_version },
+    Key { name: "DefaultWidth", default: "",
+          help: "page width in columns, empty to use the terminal width",
+          validate: validate_optional_positive },
+    Key { name: "ManSection", default: "3",
+          help: "manual section of generated pages, '3' or '3cpp'",
+          validate: validate_man_section },
+    Key { name: "CrawlMaxPages", default: "",
+          help: "pages indexed per site at most, empty for no limit",
+          validate: validate_optional_positive },
+    Key { name: "CrawlMaxDepth", default: "",
+          help: "links followed from the start page at most, empty for no limit",
+          validate: validate_optional_number },
+    Key { name: "CrawlInclude", default: "",
+          help: "space separated regexes, only matching URLs are crawled if set",
+          validate: validate_patterns },
+    Key { name: "CrawlExclude", default: "",
+          help: "space separated regexes, matching URLs are not crawled",
+          validate: validate_patterns },
+];
+
+fn validate_source(s: &str) -> Result<(), String> {
+    match Source::try_from(s) {
+        // Indexed, but its pages can't be formatted yet
+        Ok(Source::CppReference) => Err("cppreference.com pages can't be shown yet".to_owned()),
+        Ok(_)                    => Ok(()),
+        Err(e)                   => Err(e.to_string()),
+    }
+}
+
+fn validate_pager(s: &str) -> Result<(), String> {
+    Pager::try_from(s).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn validate_colour(s: &str) -> Result<(), String> {
+    Colour::try_from(s).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn validate_charset(s: &str) -> Result<(), String> {
+    Charset::try_from(s).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn validate_std_version(s: &str) -> Result<(), String> {
+    StdVersion::try_from(s).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn validate_man_section(s: &str) -> Result<(), String> {
+    ManSection::try_from(s).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn validate_bool(s: &str) -> Result<(), String> {
+    s.parse::<bool>().map(|_| ()).map_err(|_| "expected 'true' or 'false'".to_owned())
+}
+
+fn validate_path(_: &str) -> Result<(), String> {
+    Ok(())
+}
+
+fn validate_number(s: &str) -> Result<(), String> {
+    s.parse::<u64>().map(|_| ()).map_err(|_| "expected a non-negative integer".to_owned())
+}
+
+fn validate_positive(s: &str) -> Result<(), String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(()),
+        _              => Err("expected a positive integer".to_owned()),
+    }
+}
+
+fn validate_optional_positive(s: &str) -> Result<(), String> {
+    if s.is_empty() { Ok(()) } else { validate_positive(s) }
+}
+
+fn validate_optional_number(s: &str) -> Result<(), String> {
+    if s.is_empty() { Ok(()) } else { validate_number(s) }
+}
+
+fn validate_patterns(s: &str) -> Result<(), String> {
+    for pattern in s.split_whitespace() {
+        Regex::new(pattern).map_err(|e| format!("invalid regex `{}' ({})", pattern, e))?;
+    }
+    Ok(())
+}
+
+fn validate_url(s: &str) -> Result<(), String> {
+    if s.is_empty() {
+        return Ok(());
+    }
+
+    Url::parse(s).map(|_| ()).map_err(|e| format!("expected a URL ({})", e))
+}
+
+fn find_key(name: &str) -> errors::Result<&'static Key> {
+    KEYS.iter()
+        .find(|k| k.name == name)
+        .ok_or(errors::ErrorKind::UnknownConfigKey(name.to_owned(), None).into())
+}
+
 
 #[derive(Clone)]
 pub struct Config {
@@ -129,15 +447,21 @@ impl Config {
         Config::new_try_from_file(config_file).expect("Cannot create a Config struct")
     }
 
+    /// Load config from file, creating a default one if it doesn't exist.
+    ///
+    /// Unknown keys and invalid values are reported with the line they were
+    /// found on.
     pub fn new_try_from_file<P: AsRef<Path>>(config_file: P) -> errors::Result<Config> {
-        if let Ok(ini) = Ini::load_from_file(&config_file) {
-            Ok(Config {
-                config_file: config_file.as_ref().to_owned(),
-                config: RefCell::new(ini),
-            })
-        } else {
-            Config::default_config(config_file)
+        if !config_file.as_ref().exists() {
+            return Config::default_config(config_file);
         }
+
+        let config = Config {
+            config_file: config_file.as_ref().to_owned(),
+            config: RefCell::new(Ini::load_from_file(&config_file)?),
+        };
+        config.validate()?;
+        Ok(config)
     }
 
     /// Get default config.
@@ -171,12 +495,92 @@ impl Config {
         Ok(self.config.borrow().write_to_file(&self.config_file)?)
     }
 
-    /// Reload config from file.
-    fn reload(&self) -> errors::Result<()> {
-        match Ini::load_from_file(&self.config_file) {
-            Ok(ini) => { *self.config.borrow_mut() = ini; Ok(()) },
-            Err(e)  => Err(e.into()),
+    /// Check every key in the `[Settings]` section against the schema.
+    pub fn validate(&self) -> errors::Result<()> {
+        let config = self.config.borrow();
+        let settings = match config.section(Some("Settings")) {
+            Some(settings) => settings,
+            None           => return Ok(()),
+        };
+
+        for (name, value) in settings.iter() {
+            let key = find_key(name).map_err(|_| {
+                errors::ErrorKind::UnknownConfigKey(name.to_owned(), self.line_of(name))
+            })?;
+
+            if let Err(reason) = (key.validate)(value) {
+                return Err(errors::ErrorKind::InvalidConfigValue(
+                    name.to_owned(), self.line_of(name), value.to_owned(), reason).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find the line number of a key in the config file.
+    fn line_of(&self, name: &str) -> Option<usize> {
+        let file = File::open(&self.config_file).ok()?;
+        let mut in_settings = false;
+
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.ok()?;
+            let line = line.trim();
+
+            if line.starts_with('[') {
+                in_settings = line == "[Settings]";
+            } else if in_settings {
+                if let Some(pos) = line.find('=') {
+                    if line[..pos].trim() == name {
+                        return Some(i + 1);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+
+    /// Get the value of a key, falling back to its default.
+    pub fn get(&self, name: &str) -> errors::Result<String> {
+        let key = find_key(name)?;
+        Ok(self.config.borrow()
+               .get_from(Some("Settings"), key.name)
+               .unwrap_or(key.default)
+               .to_owned())
+    }
+
+    /// Validate and store the value of a key.
+    pub fn set(&self, name: &str, value: &str) -> errors::Result<()> {
+        let key = find_key(name)?;
+        (key.validate)(value).map_err(|reason| {
+            errors::ErrorKind::InvalidConfigValue(key.name.to_owned(), None, value.to_owned(), reason)
+        })?;
+
+        self.config.borrow_mut().set_to(Some("Settings"), key.name.to_owned(), value.to_owned());
+        self.save()
+    }
+
+    /// List all keys with their current values.
+    pub fn list(&self) -> Vec<(&'static Key, String)> {
+        KEYS.iter()
+            .map(|k| (k, self.get(k.name).unwrap_or(k.default.to_owned())))
+            .collect()
+    }
+
+    /// Reset a key to its default, or every key if none is given.
+    pub fn reset(&self, name: Option<&str>) -> errors::Result<()> {
+        match name {
+            Some(name) => {
+                let key = find_key(name)?;
+                self.config.borrow_mut().delete_from(Some("Settings"), key.name);
+            },
+            None => {
+                self.config.borrow_mut().delete(Some("Settings"));
+            },
         }
+
+        self.save()
     }
 
 
@@ -189,19 +593,11 @@ impl Config {
     }
 
     pub fn try_pager(&self) -> errors::Result<Pager> {
-        if let Some(s) = self.config.borrow().get_from(Some("Settings"), "Pager") {
-            return Ok(Pager::from(s));
-        }
-
-        let pager = Pager::default();
-        self.try_set_pager(pager)?;
-        self.reload()?;
-        Ok(pager)
+        Pager::try_from(&self.get("Pager")?)
     }
 
     pub fn try_set_pager(&self, pager: Pager) -> errors::Result<()> {
-        self.config.borrow_mut().set_to(Some("Settings"), "Pager".to_owned(), pager.to_string());
-        self.save()
+        self.set("Pager", &pager.to_string())
     }
 
 
@@ -214,19 +610,11 @@ impl Config {
     }
 
     pub fn try_update_man_path(&self) -> errors::Result<bool> {
-        if let Some(s) = self.config.borrow().get_from(Some("Settings"), "UpdateManPath") {
-            return Ok(UpdateManPath::from(s).0);
-        }
-
-        let update_man_path = UpdateManPath::default();
-        self.try_set_update_man_path(update_man_path.0)?;
-        self.reload()?;
-        Ok(update_man_path.0)
+        Ok(UpdateManPath::from(self.get("UpdateManPath")?.as_str()).0)
     }
 
     pub fn try_set_update_man_path(&self, update_man_path: bool) -> errors::Result<()> {
-        self.config.borrow_mut().set_to(Some("Settings"), "UpdateManPath".to_owned(), update_man_path.to_string());
-        self.save()
+        self.set("UpdateManPath", &UpdateManPath(update_man_path).to_string())
     }
 
 
@@ -239,18 +627,200 @@ impl Config {
     }
 
     pub fn try_source(&self) -> errors::Result<Source> {
-        if let Some(s) = self.config.borrow().get_from(Some("Settings"), "Source") {
-            return Ok(Source::from(s));
+        Source::try_from(&self.get("Source")?)
+    }
+
+    pub fn try_set_source(&self, source: Source) -> errors::Result<()> {
+        self.set("Source", &source.to_string())
+    }
+
+
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        self.get_optional("CacheDir").map(PathBuf::from)
+    }
+
+    pub fn index_path(&self) -> Option<PathBuf> {
+        self.get_optional("IndexPath").map(PathBuf::from)
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.get_parsed("Concurrency")
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.get_timeout("ConnectTimeout")
+    }
+
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.get_timeout("ReadTimeout")
+    }
+
+    pub fn proxy(&self) -> Option<String> {
+        self.get_optional("Proxy")
+    }
+
+    pub fn ca_bundle(&self) -> Option<PathBuf> {
+        self.get_optional("CaBundle").map(PathBuf::from)
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.get_parsed("Retries")
+    }
+
+    pub fn colour(&self) -> Colour {
+        self.get("Colour").map(|s| Colour::from(s.as_str())).unwrap_or_default()
+    }
+
+    pub fn charset(&self) -> Charset {
+        self.get("Charset").map(|s| Charset::from(s.as_str())).unwrap_or_default()
+    }
+
+    pub fn std_version(&self) -> StdVersion {
+        self.get("StdVersion").map(|s| StdVersion::from(s.as_str())).unwrap_or_default()
+    }
+
+    pub fn man_section(&self) -> ManSection {
+        self.get("ManSection").map(|s| ManSection::from(s.as_str())).unwrap_or_default()
+    }
+
+    pub fn default_width(&self) -> Option<usize> {
+        self.get_optional("DefaultWidth").and_then(|s| s.parse().ok())
+    }
+
+    pub fn crawl_max_pages(&self) -> Option<usize> {
+        self.get_optional("CrawlMaxPages").and_then(|s| s.parse().ok())
+    }
+
+    pub fn crawl_max_depth(&self) -> Option<usize> {
+        self.get_optional("CrawlMaxDepth").and_then(|s| s.parse().ok())
+    }
+
+    pub fn crawl_include(&self) -> Vec<Regex> {
+        self.get_patterns("CrawlInclude")
+    }
+
+    pub fn crawl_exclude(&self) -> Vec<Regex> {
+        self.get_patterns("CrawlExclude")
+    }
+
+
+    /// Get a value of an optional key, `None` if it's empty.
+    fn get_optional(&self, name: &str) -> Option<String> {
+        self.get(name).ok().and_then(|s| if s.is_empty() { None } else { Some(s) })
+    }
+
+    /// Get a list of space separated regexes, skipping invalid ones.
+    fn get_patterns(&self, name: &str) -> Vec<Regex> {
+        self.get(name).ok().map_or(Vec::new(), |s| {
+            s.split_whitespace().filter_map(|p| Regex::new(p).ok()).collect()
+        })
+    }
+
+    /// Get a timeout in seconds, `None` if it's disabled.
+    fn get_timeout(&self, name: &str) -> Option<Duration> {
+        match self.get_parsed(name) {
+            0    => None,
+            secs => Some(Duration::from_secs(secs)),
         }
+    }
+
+    /// Get a numeric value, falling back to the default if it can't be parsed.
+    fn get_parsed<T: ::std::str::FromStr + Default>(&self, name: &str) -> T {
+        self.get(name).ok()
+            .and_then(|s| s.parse().ok())
+            .or(find_key(name).ok().and_then(|k| k.default.parse().ok()))
+            .unwrap_or_default()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::path::PathBuf;
 
-        let source = Source::default();
-        self.try_set_source(source)?;
-        self.reload()?;
-        Ok(source)
+    use super::Config;
+    use ::errors::ErrorKind;
+
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let dir = env::temp_dir().join("cppman-rs-test-config");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+        path
     }
 
-    pub fn try_set_source(&self, source: Source) -> errors::Result<()> {
-        self.config.borrow_mut().set_to(Some("Settings"), "Source".to_owned(), source.to_string());
-        self.save()
+    #[test]
+    fn test_invalid_value_reports_line() {
+        let path = config_file("invalid.cfg", "[Settings]\nSource=cplusplus.com\nPager=more\n");
+
+        match Config::new_try_from_file(&path) {
+            Err(e) => match *e.kind() {
+                ErrorKind::InvalidConfigValue(ref key, line, ref value, _) => {
+                    assert_eq!(key, "Pager");
+                    assert_eq!(line, Some(3));
+                    assert_eq!(value, "more");
+                },
+                ref k => panic!("unexpected error: {}", k),
+            },
+            Ok(_) => panic!("invalid config accepted"),
+        }
+    }
+
+    #[test]
+    fn test_unsupported_source() {
+        let path = config_file("cppreference.cfg", "[Settings]\nSource=cppreference.com\n");
+
+        match Config::new_try_from_file(&path) {
+            Err(e) => match *e.kind() {
+                ErrorKind::InvalidConfigValue(ref key, line, _, _) => {
+                    assert_eq!(key, "Source");
+                    assert_eq!(line, Some(2));
+                },
+                ref k => panic!("unexpected error: {}", k),
+            },
+            Ok(_) => panic!("cppreference.com accepted as source"),
+        }
+    }
+
+    #[test]
+    fn test_unknown_key_reports_line() {
+        let path = config_file("unknown.cfg", "[Settings]\nSorce=cplusplus.com\n");
+
+        match Config::new_try_from_file(&path) {
+            Err(e) => match *e.kind() {
+                ErrorKind::UnknownConfigKey(ref key, line) => {
+                    assert_eq!(key, "Sorce");
+                    assert_eq!(line, Some(2));
+                },
+                ref k => panic!("unexpected error: {}", k),
+            },
+            Ok(_) => panic!("unknown key accepted"),
+        }
+    }
+
+    #[test]
+    fn test_get_does_not_write() {
+        let path = config_file("readonly.cfg", "[Settings]\n");
+        let config = Config::new_try_from_file(&path).unwrap();
+
+        assert_eq!(config.get("StdVersion").unwrap(), "c++14");
+        assert_eq!(config.concurrency(), 4);
+        assert_eq!(fs::metadata(&path).unwrap().len(), "[Settings]\n".len() as u64);
+    }
+
+    #[test]
+    fn test_set_and_reset() {
+        let path = config_file("set.cfg", "[Settings]\n");
+        let config = Config::new_try_from_file(&path).unwrap();
+
+        assert!(config.set("DefaultWidth", "zero").is_err());
+        config.set("DefaultWidth", "80").unwrap();
+        assert_eq!(Config::new_try_from_file(&path).unwrap().default_width(), Some(80));
+
+        config.reset(Some("DefaultWidth")).unwrap();
+        assert_eq!(Config::new_try_from_file(&path).unwrap().default_width(), None);
     }
 }
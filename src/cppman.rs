@@ -1,49 +1,65 @@
-use std::borrow::Borrow;
 use std::cell::{Cell, RefCell};
-use std::collections::{HashMap, HashSet};
-use std::fs::{self, File};
-use std::io::{self, Read, Write};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
 use std::ops::AddAssign;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::process::{Command, ExitStatus};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 
-use flate2::Compression;
-use flate2::write::GzEncoder;
 use isatty::stdout_isatty;
-use regex::Regex;
-use reqwest;
-use rusqlite::{self, Connection};
+use ordermap::OrderMap;
+use regex::{self, Regex};
+use rusqlite::{self, Connection, SQLITE_OPEN_READ_ONLY};
+use rusqlite::types::ToSql;
 use url::Url;
 
-use ::crawler::{Crawler, Document};
+use ::config::{Colour, Pager, Source, StdVersion};
+use ::crawler::{Crawler, Document, FollowMode, Limits};
 use ::environ::Environ;
 use ::errors;
-use ::util::get_width;
+use ::example::{self, Example};
+use ::formatter::{self, alias_page, alias_target, class_info, decode_entities, html2groff, page_info,
+                  select_sections, split_names};
+use ::frontier::Frontier;
+use ::http::HttpConfig;
+use ::index::{self, ClassInfo, Kind, PageInfo};
+use ::mandb;
+use ::output::{EntryWriter, OutputFormat, PageEntry};
+use ::transport::{self, Transport};
+use ::util::{get_width, groff2man_charset, groff_device, read_gz_file, write_gz_file, write_if_changed};
+use ::vim::ensure_pager_config;
+
+
+/// Pager script written to the data directory when none is installed.
+static PAGER_SCRIPT: &'static str = include_str!("../data/pager.sh");
 
 
 lazy_static! {
     static ref H1_INNER_HTML: Regex = Regex::new("<h1[^>]*>(.+?)</h1>").unwrap();
     static ref TAG: Regex = Regex::new("<([^>]+)>").unwrap();
-    static ref GREATER_THAN: Regex = Regex::new("&gt;").unwrap();
-    static ref LESSER_THAN: Regex = Regex::new("&lt;").unwrap();
-
-    static ref OPERATOR: Regex = Regex::new("^\\s*(.*?::(?:operator)?)([^:]*)\\s*$").unwrap();
 }
 
 
 pub struct Cppman {
-    crawler: Crawler,
-    results: HashSet<(String, Url)>,
     forced: bool,
     success_count: Cell<Option<u32>>,
     failure_count: Cell<Option<u32>>,
     force_columns: Option<usize>,
+    /// Sections pages are reduced to when viewed, all if `None`.
+    sections: Option<Vec<String>>,
+    /// Whether to keep stderr clean, for full screen interfaces.
+    quiet: bool,
 
     blacklist: Vec<Url>,
     name_exceptions: Vec<String>,
     env: Environ,
 
-    db_conn: Option<RefCell<Connection>>,
+    db_conn: RefCell<Option<Connection>>,
+    index_conn: RefCell<Option<Connection>>,
+    transport: RefCell<Option<Rc<Transport>>>,
 }
 
 impl Cppman {
@@ -53,58 +69,112 @@ impl Cppman {
 
     pub fn new(forced: Option<bool>, force_columns: Option<usize>, env: &Environ) -> Cppman {
         Cppman {
-            crawler: Crawler::new(),
-            results: HashSet::new(),
             forced: forced.unwrap_or(false),
             success_count: Cell::new(None),
             failure_count: Cell::new(None),
             force_columns: force_columns,
+            sections: None,
+            quiet: false,
 
             blacklist: Vec::new(),
             name_exceptions: vec!["http://www.cplusplus.com/reference/string/swap/".to_owned()],
             env: env.clone(),
 
-            db_conn: None,
+            db_conn: RefCell::new(None),
+            index_conn: RefCell::new(None),
+            transport: RefCell::new(None),
         }
     }
 
+    /// Only show the sections called `sections` of the pages viewed, e.g.
+    /// `SYNOPSIS` and `RETURN VALUE`.
+    pub fn set_sections(&mut self, sections: Option<Vec<String>>) {
+        self.sections = sections;
+    }
+
+    /// Don't report retried downloads and other non-fatal problems on
+    /// stderr, which would be drawn over a full screen interface.
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
     /// Extract man page name from web page.
     fn extract_name(&self, data: &str) -> errors::Result<String> {
         H1_INNER_HTML.captures(data)
             .ok_or(errors::ErrorKind::NoCaptures.into())
             .and_then(|cap| {
                 cap.get(1).map(|m| {
-                    let mut name = m.as_str().to_owned();
-                    name = TAG.replace(&name, "").into_owned();
-                    name = GREATER_THAN.replace(&name, ">").into_owned();
-                    name = LESSER_THAN.replace(&name, ">").into_owned();
-                    name
+                    decode_entities(&TAG.replace_all(m.as_str(), ""))
                 }).ok_or(errors::ErrorKind::NoCapturesIndex(1).into())
             })
     }
 
     /// Rebuild index database from cplusplus.com and cppreference.com.
-    pub fn rebuild_index(&self) {
-        // Continue even if it fails
-        let _ = fs::remove_file(&self.env.index_db_re);
+    ///
+    /// The index is built in a separate database next to it, which also
+    /// holds the crawl state until both sites are done, so that an
+    /// interrupted rebuild resumes where it stopped unless `restart` is set.
+    pub fn rebuild_index(&self, restart: bool) -> errors::Result<()> {
+        let partial = self.partial_index_path();
+        if restart {
+            // Continue even if it fails
+            let _ = fs::remove_file(&partial);
+        }
 
-        /*let db_conn = try!(Connection::open(self.env.index_db_re).map_err(new_io_error));
-        self.db_conn.set(Some(db_conn));
+        if let Some(dir) = partial.parent() {
+            fs::create_dir_all(dir)?;
+        }
 
-        db_conn.execute("CREATE TABLE \"cplusplus.com\" \
-                         (name VARCHAR(255), url VARCHAR(255))")
-        db_conn.execute("CREATE TABLE \"cppreference.com\" \
-                         (name VARCHAR(255), url VARCHAR(255))")*/
+        let db_conn = Connection::open(&partial)?;
+        index::upgrade(&db_conn)?;
+        *self.db_conn.borrow_mut() = Some(db_conn);
+
+        let limits = Limits::from_config(&self.env.config);
+        let mut summaries = Vec::new();
+
+        for &(source, root) in &[(Source::CPlusPlus, "http://www.cplusplus.com/reference/"),
+                                 (Source::CppReference, "http://en.cppreference.com/w/cpp/")] {
+            let mut crawler = Crawler::with_frontier(Frontier::open(&partial)?);
+            crawler.set_follow_mode(FollowMode::SamePath);
+            crawler.set_limits(limits.clone());
+            crawler.set_transport(self.transport()?);
+            summaries.push(crawler.crawl(root, |doc| {
+                if let Err(e) = self.process_document(source, doc) {
+                    println!("Skipping '{}': {}", doc.url, e);
+                }
+            })?);
+        }
+
+        *self.db_conn.borrow_mut() = None;
+
+        for summary in &summaries {
+            println!("{}", summary);
+        }
+
+        {
+            let conn = Connection::open(&partial)?;
+            Frontier::drop_tables(&conn)?;
+            conn.execute_batch("VACUUM")?;
+        }
+        fs::rename(&partial, &self.env.index_db_re)?;
 
-        unimplemented!();
+        Ok(())
+    }
+
+    /// Index database being rebuilt.
+    fn partial_index_path(&self) -> PathBuf {
+        let mut path = self.env.index_db_re.clone().into_os_string();
+        path.push(".partial");
+        PathBuf::from(path)
     }
 
     /// callback to insert index
-    fn process_document(&mut self, doc: Document) -> errors::Result<()> {
+    fn process_document(&self, source: Source, doc: &Document) -> errors::Result<()> {
         if !self.blacklist.contains(&doc.url) {
             println!("Indexing '{}' ...", doc.url);
             let name = self.extract_name(&doc.text)?;
-            self.results.insert((name, doc.url));
+            let info = page_info(source, &doc.text);
+            self.insert_index(source, &name, doc.url.as_str(), &info)?;
         } else {
             println!("Skipping blacklisted page '{}' ...", doc.url);
         }
@@ -113,23 +183,11 @@ impl Cppman {
     }
 
     /// callback to insert index
-    fn insert_index(&self, table: &str, name: &str, url: &str) -> errors::Result<()> {
-        let mut names = name.split(',').map(str::to_owned).collect::<Vec<_>>();
-
-        if names.len() > 1 {
-            if let Some(caps) = OPERATOR.captures(&names[0].clone()) {
-                let prefix = caps.get(1).ok_or(errors::ErrorKind::NoCapturesIndex(1))?.as_str().to_owned();
-                names[0] = caps.get(2).ok_or(errors::ErrorKind::NoCapturesIndex(2))?.as_str().to_owned();
-                names = names.into_iter().map(|n| prefix.to_owned() + &n).collect::<Vec<_>>();
-            }
-        }
-
-        for n in names {
-            match self.db_conn {
-                Some(ref db_conn) => {
-                    db_conn.borrow().execute(&format!(
-                        "INSERT INTO \"{}\" (name, url) VALUES (?, ?)", table), &[&n.trim(), &url])?;
-                },
+    fn insert_index(&self, source: Source, name: &str, url: &str, info: &PageInfo)
+            -> errors::Result<()> {
+        for n in split_names(name) {
+            match *self.db_conn.borrow() {
+                Some(ref db_conn) => index::insert(db_conn, source, &n, url, info)?,
                 None => return Err(errors::ErrorKind::NoDbConn.into()),
             }
         }
@@ -162,10 +220,11 @@ impl Cppman {
 
         {
             let conn = Connection::open(&self.env.index_db)?;
+            index::upgrade(&conn)?;
 
             let source = self.env.config.source();
             println!("Caching manpages from {} ...", source);
-            let mut stmt = conn.prepare(&format!("SELECT * FROM \"{}\"", source))?;
+            let mut stmt = conn.prepare(&format!("SELECT name, url FROM \"{}\"", source))?;
             let data = stmt.query_and_then(&[], |&ref row| {
                 let a = row.get_checked(0)?;
                 let b = row.get_checked(1)?;
@@ -173,26 +232,35 @@ impl Cppman {
             })?.collect::<Result<Vec<(String, String)>, rusqlite::Error>>();
 
             if let Ok(d) = data {
+                // Names of the same page share one file, the others get aliases
+                let mut pages = OrderMap::<String, Vec<String>>::new();
                 for (name, url) in d {
-                    let mut retries = 3;
-                    println!("Caching {} ...", name);
-                    while retries > 0 {
-                        match self.cache_man_page(&source.to_string(), &url, &name) {
-                            Ok(_)  => break,
-                            Err(_) => {
-                                println!("Retrying ...");
-                                retries -= 1;
-                            },
-                        }
-                    }
+                    pages.entry(url).or_insert_with(Vec::new).push(name);
+                }
 
-                    if retries == 0 {
-                        println!("Error caching {} ...", name);
-                        update_add_cell_op(&self.failure_count, 1);
-                    } else {
-                        update_add_cell_op(&self.success_count, 1);
-                    }
+                // Pages already cached are kept unless forced
+                let source = source.to_string();
+                let urls = pages.iter()
+                    .filter(|&(_, names)| self.forced || !self.get_page_path(&source, &names[0]).exists())
+                    .map(|(url, _)| url.clone())
+                    .collect::<Vec<_>>();
+                for _ in urls.len()..pages.len() {
+                    update_add_cell_op(&self.success_count, 1);
                 }
+
+                // Transient failures are already retried by the HTTP layer
+                self.fetch_all(urls, |url, data| {
+                    let names = &pages[url];
+                    println!("Caching {} ...", names[0]);
+
+                    match data.and_then(|data| self.write_man_page(&source, &data, names).map_err(|e| e.to_string())) {
+                        Ok(_)  => update_add_cell_op(&self.success_count, 1),
+                        Err(e) => {
+                            println!("Error caching {}: {}", names[0], e);
+                            update_add_cell_op(&self.failure_count, 1);
+                        },
+                    }
+                });
             }
         }
 
@@ -202,105 +270,653 @@ impl Cppman {
     }
 
     /// callback to cache new man page
-    fn cache_man_page(&self, source: &str, url: &str, name: &str) -> errors::Result<()> {
+    ///
+    /// The page is stored under the first of `names`, the other ones become
+    /// alias pages including it.
+    fn cache_man_page(&self, source: &str, url: &str, names: &[String]) -> errors::Result<()> {
         // Skip if already exists, override if forced flag is true
-        let outname = self.get_page_path(source, name);
-        if outname.exists() && !self.forced {
+        if self.get_page_path(source, &names[0]).exists() && !self.forced {
             return Ok(());
         }
 
-        fs::create_dir_all(self.env.man_dir.join(source))?;
-
         // There are often some errors in the HTML, for example: missing closing
         // tag. We use fixupHTML to fix this.
-        let mut data = String::new();
-        let mut resp = reqwest::get(url)?;
-        resp.read_to_string(&mut data)?;
+        let data = self.transport()?.fetch_text(&Url::parse(url)?)?;
+        self.write_man_page(source, &data, names)
+    }
 
-        let html2groff: fn(&str, &str) -> String;
+    /// Download the pages at `urls` with `Concurrency` threads, each with a
+    /// transport of its own, and pass each one to `f` as it arrives, with the
+    /// message of the error if it couldn't be downloaded.
+    fn fetch_all<F>(&self, urls: Vec<String>, mut f: F)
+            where F: FnMut(&str, Result<String, String>) {
+        let queue = Arc::new(Mutex::new(urls.into_iter()));
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..self.env.config.concurrency() {
+            let (queue, sender) = (queue.clone(), sender.clone());
+            let (mode, config) = (self.env.fetch.clone(), self.http_config());
+
+            thread::spawn(move || {
+                let transport = transport::from_mode(&mode, &config).map_err(|e| e.to_string());
+                loop {
+                    let url = match queue.lock().unwrap().next() {
+                        Some(url) => url,
+                        None      => break,
+                    };
+
+                    let data = transport.clone().and_then(|transport| {
+                        Url::parse(&url).map_err(|e| e.to_string())
+                            .and_then(|u| transport.fetch_text(&u).map_err(|e| e.to_string()))
+                    });
+                    // Stop when nobody is waiting for the pages anymore
+                    if sender.send((url, data)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
 
-        match &source[..source.len()-4] {
-            "cplusplus"    => html2groff = ::formatter::cplusplus::html2groff,
-            "cppreference" => html2groff = ::formatter::cppreference::html2groff,
-            s => return Err(errors::ErrorKind::WrongSource(s.to_owned()).into()),
+        // The workers hold the remaining senders, the loop ends with them
+        drop(sender);
+        for (url, data) in receiver {
+            f(&url, data);
         }
+    }
+
+    /// Write the man page of `names` from its HTML `data`, with the aliases.
+    fn write_man_page(&self, source: &str, data: &str, names: &[String]) -> errors::Result<()> {
+        let name = &names[0];
+        let section = self.env.config.man_section();
+        let outname = self.get_page_path(source, name);
+        fs::create_dir_all(self.env.man_dir.join(source))?;
+
+        // Keep the raw HTML so that pages can be re-rendered with `--reformat`
+        // without hitting the network again.
+        write_gz_file(self.get_html_path(source, name), data)?;
 
-        let groff_text = html2groff(&data, name);
+        let groff_text = html2groff(Source::try_from(source)?, data, names, section)?;
 
-        let mut file = File::create(outname)?;
-        let mut enc = GzEncoder::new(file, Compression::Default);
-        enc.write_all(groff_text.as_bytes())?;
-        enc.finish()?;
+        write_gz_file(outname, &groff_text)?;
+        self.record_class(Source::try_from(source)?, name, &groff_text);
+
+        let target = get_normalized_page_name(name);
+        for alias in &names[1..] {
+            let path = self.get_page_path(source, alias);
+            if self.forced || !path.exists() {
+                write_gz_file(path, &alias_page(&target, section))?;
+            }
+        }
 
         Ok(())
     }
 
+    /// Keep the members and bases of a class page in the index for
+    /// `members`, if it is one.
+    fn record_class(&self, source: Source, name: &str, groff_text: &str) {
+        if let Some(info) = class_info(source, groff_text) {
+            self.store_class(source, name, &info);
+        }
+    }
+
+    /// Keep `info` in the index, which only saves reading the page again:
+    /// pages are written and shown without it when the index can't be
+    /// written to.
+    fn store_class(&self, source: Source, name: &str, info: &ClassInfo) {
+        // Pages can be reformatted without an index, and a read-only one is
+        // left alone
+        if !self.env.index_db.exists() || !is_writable(&self.env.index_db) {
+            return;
+        }
+
+        if let Err(e) = self.with_index(|conn| index::store_class(conn, source, name, info)) {
+            if !self.quiet {
+                let _ = writeln!(&mut io::stderr(), "cppman-rs: cannot record the class {}: {}", name, e);
+            }
+        }
+    }
+
+    /// All index names of the page `name` refers to, starting with `name`.
+    fn page_names(&self, source: &str, name: &str) -> Vec<String> {
+        let others = self.with_index(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT name FROM \"{0}\" WHERE name != ?1 AND url = \
+                 (SELECT url FROM \"{0}\" WHERE name = ?1 LIMIT 1)", source))?;
+            let names = stmt.query_map(&[&name], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(names)
+        });
+
+        let mut names = vec![name.to_owned()];
+        // Without an index the page only has its own name
+        names.extend(others.unwrap_or_default());
+        names
+    }
+
+    /// Index names of `source` by the name of the files of their page, which
+    /// can't be told apart from other names with `/` replaced by `_`. Empty
+    /// without an index.
+    fn names_by_file(&self, source: Source) -> BTreeMap<String, String> {
+        let names = self.with_index(|conn| {
+            let mut stmt = conn.prepare(&format!("SELECT name FROM \"{}\"", source))?;
+            let names = stmt.query_map(&[], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(names)
+        });
+
+        let mut names_by_file = BTreeMap::new();
+        for name in names.unwrap_or_default() {
+            names_by_file.entry(get_normalized_page_name(&name)).or_insert(name);
+        }
+        names_by_file
+    }
+
+    /// Path of the page `name` is viewed from, caching it first if needed and
+    /// following alias pages.
+    fn cached_page(&self, name: &str, url: &str) -> errors::Result<PathBuf> {
+        let source = self.env.source.to_string();
+        let page_path = self.get_page_path(&source, name);
+        if self.forced || !page_path.exists() {
+            self.cache_man_page(&source, url, &self.page_names(&source, name))?;
+        }
+
+        self.resolve_alias(&source, page_path)
+    }
+
+    fn resolve_alias(&self, source: &str, page_path: PathBuf) -> errors::Result<PathBuf> {
+        let groff_text = read_gz_file(&page_path)?;
+        Ok(match alias_target(&groff_text) {
+            Some(target) => self.env.man_dir.join(source).join(format!("{}.gz", target)),
+            None         => page_path,
+        })
+    }
+
+    /// Regenerate all cached man pages from stored HTML.
+    pub fn reformat(&self) -> errors::Result<()> {
+        let mut changed_count = 0;
+        let mut unchanged_count = 0;
+
+        for &source in &[Source::CPlusPlus, Source::CppReference] {
+            let html_dir = self.env.cache_dir.join("html").join(source.to_string());
+            if !html_dir.exists() {
+                continue;
+            }
+
+            println!("Reformatting manpages from {} ...", source);
+            let names_by_file = self.names_by_file(source);
+
+            for entry in fs::read_dir(&html_dir)? {
+                let path = entry?.path();
+                let stem = match path.file_name().and_then(|f| f.to_str()) {
+                    Some(f) if f.ends_with(".html.gz") => f[..f.len()-8].to_owned(),
+                    _ => continue,
+                };
+                // Without an index the file name is the best we have
+                let name = names_by_file.get(&stem).cloned().unwrap_or(stem);
+
+                let data = read_gz_file(&path)?;
+                let names = self.page_names(&source.to_string(), &name);
+                let section = self.env.config.man_section();
+                let groff_text = html2groff(source, &data, &names, section)?;
+                self.record_class(source, &name, &groff_text);
+
+                // Names added to the index since the page was cached
+                let target = get_normalized_page_name(&name);
+                for alias in &names[1..] {
+                    let path = self.get_page_path(&source.to_string(), alias);
+                    if !path.exists() {
+                        write_gz_file(path, &alias_page(&target, section))?;
+                    }
+                }
+
+                let outname = self.get_page_path(&source.to_string(), &name);
+                let old_text = read_gz_file(&outname).ok();
+                if old_text.map_or(false, |t| same_groff(&t, &groff_text)) {
+                    unchanged_count += 1;
+                    continue;
+                }
+
+                println!("Changed: {}", name);
+                write_gz_file(outname, &groff_text)?;
+                changed_count += 1;
+            }
+        }
+
+        println!("\n{} manual pages changed.", changed_count);
+        println!("{} manual pages unchanged.", unchanged_count);
+        self.update_mandb(Some(false))
+    }
+
     /// Clear all cache in man3
     pub fn clear_cache(&self) -> errors::Result<()> {
+        let html_dir = self.env.cache_dir.join("html");
+        if html_dir.exists() {
+            fs::remove_dir_all(&html_dir)?;
+        }
+
         fs::remove_dir_all(&self.env.man_dir).map_err(Into::into)
     }
 
-    /// Call viewer.sh to view man page
-    pub fn man(&self, pattern: &str) -> errors::Result<()> {
-        let avail = fs::read_dir(self.env.man_dir.join(self.env.source.to_string()))?;
-        let avail = avail.collect::<Result<Vec<_>, _>>().unwrap_or(Vec::new()).iter().map(|d| d.path()).collect::<Vec<_>>();
+    /// Look up the index entry best matching `pattern`.
+    ///
+    /// Tries an exact match first, then the same name in the `std`
+    /// namespace, then the shortest name containing `pattern`.
+    pub fn lookup(&self, pattern: &str) -> errors::Result<(String, String)> {
+        self.with_index(|conn| self.lookup_in(conn, pattern))
+    }
 
-        if !self.env.index_db.exists() {
-            return Err(errors::ErrorKind::NoIndexDb.into());
+    fn lookup_in(&self, conn: &Connection, pattern: &str) -> errors::Result<(String, String)> {
+        macro_rules! get_pair {
+            ($sql:expr, $param:expr) => {
+                conn.query_row_and_then(&format!($sql, self.env.source), &[&$param],
+                    |row| {
+                        let a: String = row.get_checked(0)?;
+                        let b: String = row.get_checked(1)?;
+                        Ok((a, b))
+                    }).map_err(|e: rusqlite::Error| e)
+            }
         }
 
-        let page_name;
-        let url;
+        // Try direct match
+        get_pair!("SELECT name,url FROM \"{}\" \
+                  WHERE name=? ORDER BY LENGTH(name)", pattern).or(
+            // Try standard library
+            get_pair!("SELECT name,url FROM \"{}\" \
+                      WHERE name=? ORDER BY LENGTH(name)", format!("std::{}", pattern))).or(
+            get_pair!("SELECT name,url FROM \"{}\" \
+                      WHERE name LIKE ? ORDER BY LENGTH(name)", format!("%{}%", pattern))).map_err(|_| {
+                errors::ErrorKind::NoManualEntry(pattern.to_owned()).into()
+            })
+    }
 
-        {
-            let conn = Connection::open(&self.env.index_db)?;
+    /// Search the index for names containing `pattern`, shortest first.
+    pub fn search(&self, pattern: &str) -> errors::Result<Vec<(String, String)>> {
+        self.with_index(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT name,url FROM \"{}\" WHERE name \
+                 LIKE ? ORDER BY LENGTH(name)",
+                self.env.source))?;
+            let selected = stmt.query_and_then(&[&format!("%{}%", pattern)], |&ref row| {
+                let a = row.get_checked(0)?;
+                let b = row.get_checked(1)?;
+                Ok((a, b))
+            })?.collect::<Result<Vec<(String, String)>, rusqlite::Error>>()?;
+
+            Ok(selected)
+        })
+    }
+
+    /// Index names declared in `header`, which may be given with or without
+    /// angle brackets, grouped by kind. Names of unknown kind come last.
+    pub fn header_symbols(&self, header: &str) -> errors::Result<Vec<(Option<Kind>, Vec<String>)>> {
+        let header = format!("<{}>", header.trim().trim_left_matches('<').trim_right_matches('>'));
+
+        let rows = self.with_index(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT DISTINCT kind, name FROM \"{}\" WHERE header = ? ORDER BY name",
+                self.env.source))?;
+            let rows = stmt.query_map(&[&header], |row| {
+                (row.get::<_, Option<String>>(0), row.get::<_, String>(1))
+            })?.collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })?;
+
+        let mut groups = BTreeMap::new();
+        for (kind, name) in rows {
+            let kind = kind.and_then(|k| Kind::try_from(&k).ok());
+            groups.entry((kind.is_none(), kind)).or_insert_with(Vec::new).push(name);
+        }
+
+        Ok(groups.into_iter().map(|((_, kind), names)| (kind, names)).collect())
+    }
+
+    /// Headers declaring the symbol `pattern`, with or without `std::`, or
+    /// else the one `lookup` resolves it to.
+    pub fn which_header(&self, pattern: &str) -> errors::Result<Vec<String>> {
+        let bare = if pattern.starts_with("std::") { &pattern[5..] } else { pattern };
+        let qualified = format!("std::{}", bare);
+
+        let headers_where = |condition: &str, params: &[&ToSql]| {
+            self.with_index(|conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT DISTINCT header FROM \"{}\" WHERE {} AND header IS NOT NULL ORDER BY header",
+                    self.env.source, condition))?;
+                let headers = stmt.query_map(params, |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(headers)
+            })
+        };
+
+        let headers = headers_where("name IN (?, ?)", &[&bare, &qualified])?;
+        if !headers.is_empty() {
+            return Ok(headers);
+        }
+
+        let (_, url) = self.lookup(pattern)?;
+        headers_where("url = ?", &[&url])
+    }
+
+    /// Print the index names declared in `header` grouped by kind.
+    pub fn list_header(&self, header: &str) -> errors::Result<()> {
+        let groups = self.header_symbols(header)?;
+        if groups.is_empty() {
+            return Err(errors::ErrorKind::NoMatch(header.to_owned()).into());
+        }
+
+        for (i, (kind, names)) in groups.into_iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("{}:", kind.map_or("other".to_owned(), |k| k.to_string()));
+            for name in names {
+                println!("    {}", name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Print the headers declaring `pattern`, one per line.
+    pub fn print_which_header(&self, pattern: &str) -> errors::Result<()> {
+        let headers = self.which_header(pattern)?;
+        if headers.is_empty() {
+            return Err(errors::ErrorKind::NoMatch(pattern.to_owned()).into());
+        }
+
+        for header in headers {
+            println!("{}", header);
+        }
+
+        Ok(())
+    }
+
+    /// Members and bases of the class `pattern` resolves to, with its index
+    /// name. They are recorded when the page is cached, pages cached before
+    /// are read again, and missing ones are cached first.
+    pub fn class_info(&self, pattern: &str) -> errors::Result<(String, ClassInfo)> {
+        let (name, url) = self.lookup(pattern)?;
+        let source = self.env.source;
+
+        if let Some(info) = self.with_index(|conn| index::load_class(conn, source, &name))? {
+            return Ok((name, info));
+        }
+
+        let groff_text = read_gz_file(&self.cached_page(&name, &url)?)?;
+        let info = class_info(source, &groff_text)
+            .ok_or_else(|| errors::Error::from(errors::ErrorKind::NotAClass(name.clone())))?;
+        self.store_class(source, &name, &info);
+        Ok((name, info))
+    }
+
+    /// Base classes of `pattern` as `(depth, name)` rows, the class itself
+    /// first at depth 0, each class followed by its direct bases.
+    pub fn class_hierarchy(&self, pattern: &str) -> errors::Result<Vec<(usize, String)>> {
+        let (name, info) = self.class_info(pattern)?;
+
+        // Pages list indirect bases too, those of a listed base are not direct
+        let mut bases_of = BTreeMap::new();
+        for base in &info.bases {
+            let theirs = match self.class_info(base) {
+                Ok((_, base_info)) => base_info.bases,
+                Err(e) => match *e.kind() {
+                    errors::ErrorKind::NoManualEntry(_) | errors::ErrorKind::NotAClass(_) => Vec::new(),
+                    _ => return Err(e),
+                },
+            };
+            bases_of.insert(base.clone(), theirs);
+        }
+        bases_of.insert(name.clone(), info.bases);
+
+        let mut rows = Vec::new();
+        push_hierarchy(&bases_of, &name, &mut Vec::new(), &mut rows);
+        Ok(rows)
+    }
+
+    /// Print the members of the class `pattern` as a table, preceded by its
+    /// base classes if `hierarchy` is set.
+    pub fn print_members(&self, pattern: &str, hierarchy: bool) -> errors::Result<()> {
+        if hierarchy {
+            for (depth, class) in self.class_hierarchy(pattern)? {
+                println!("{}{}", "  ".repeat(depth), class);
+            }
+            println!();
+        }
+
+        let (_, info) = self.class_info(pattern)?;
+        let rows = info.members.iter().map(|m| {
+            (m.kind.to_string(), m.name.as_str(), m.origin.as_ref().map_or("", String::as_str),
+             m.description.as_str())
+        }).collect::<Vec<_>>();
+
+        let kind_width = rows.iter().map(|r| r.0.len()).chain(Some(4)).max().unwrap();
+        let name_width = rows.iter().map(|r| r.1.chars().count()).chain(Some(6)).max().unwrap();
+        let origin_width = rows.iter().map(|r| r.2.chars().count()).chain(Some(4)).max().unwrap();
+
+        println!("{:3$}  {:4$}  {:5$}  DESCRIPTION", "KIND", "MEMBER", "FROM",
+                 kind_width, name_width, origin_width);
+        for (kind, name, origin, description) in rows {
+            println!("{:4$}  {:5$}  {:6$}  {}", kind, name, origin, description,
+                     kind_width, name_width, origin_width);
+        }
+
+        Ok(())
+    }
 
-            macro_rules! get_pair {
-                ($sql:expr) => {
-                    conn.query_row_and_then(&format!($sql, self.env.source, pattern), &[],
-                        |row| {
-                            let a: String = row.get_checked(0)?;
-                            let b: String = row.get_checked(1)?;
-                            Ok((a, b))
-                        }).map_err(|e: rusqlite::Error| e)
+    /// Example of the page `pattern` resolves to, with the page's index name.
+    /// It is read from the stored HTML of the page if it was cached.
+    pub fn example(&self, pattern: &str) -> errors::Result<(String, Example)> {
+        let (name, url) = self.lookup(pattern)?;
+        let source = self.env.source;
+
+        let html_path = self.get_html_path(&source.to_string(), &name);
+        let data = if html_path.exists() {
+            read_gz_file(&html_path)?
+        } else {
+            self.transport()?.fetch_text(&Url::parse(&url)?)?
+        };
+
+        match formatter::example(source, &data) {
+            Some(example) => Ok((name, example)),
+            None          => Err(errors::ErrorKind::NoExample(name).into()),
+        }
+    }
+
+    /// Print the example of `pattern`, or save it to `out_dir` and with `run`
+    /// compile and run it, failing if it prints something other than the
+    /// documented output.
+    pub fn run_example(&self, pattern: &str, out_dir: Option<&Path>, run: bool) -> errors::Result<()> {
+        let (name, snippet) = self.example(pattern)?;
+        if out_dir.is_none() && !run {
+            print!("{}", snippet.code);
+            return Ok(());
+        }
+
+        let dir = out_dir.map_or_else(|| self.env.cache_dir.join("examples"), Path::to_path_buf);
+        let path = example::save(&snippet, &dir, &name)?;
+        if !run {
+            println!("{}", path.display());
+            return Ok(());
+        }
+
+        let output = example::compile_and_run(&path, self.example_std_version(&name))?;
+        print!("{}", output);
+
+        match snippet.output {
+            Some(ref documented) if !example::same_output(&output, documented) => {
+                println!("\nDocumented output:\n{}", documented.trim_right());
+                Err(errors::ErrorKind::ExampleOutputDiffers(name).into())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Standard examples are compiled for, `StdVersion` or the one that
+    /// introduced the page if it's newer.
+    fn example_std_version(&self, name: &str) -> StdVersion {
+        let configured = self.env.config.std_version();
+        let page = self.with_index(|conn| {
+            let version = conn.query_row(
+                &format!("SELECT std_version FROM \"{}\" WHERE name = ? LIMIT 1", self.env.source),
+                &[&name], |row| row.get::<_, Option<String>>(0))?;
+            Ok(version)
+        });
+
+        match page.ok().and_then(|v| v).and_then(|v| StdVersion::try_from(&v).ok()) {
+            Some(version) if version > configured => version,
+            _                                     => configured,
+        }
+    }
+
+    /// Complete a page name for the shell.
+    ///
+    /// Names in the `std` namespace also match without the `std::` prefix, and
+    /// are then completed without it, the same way `lookup` resolves them.
+    pub fn complete(&self, prefix: &str) -> errors::Result<Vec<String>> {
+        let std_prefix = format!("std::{}", prefix);
+
+        let mut names = self.with_index(|conn| {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT DISTINCT name FROM \"{}\" \
+                 WHERE substr(name, 1, ?) = ? OR substr(name, 1, ?) = ? ORDER BY name",
+                self.env.source))?;
+            let names = stmt.query_map(&[&(prefix.chars().count() as i64), &prefix,
+                                         &(std_prefix.chars().count() as i64), &std_prefix],
+                                       |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(names)
+        })?;
+
+        if !prefix.starts_with("std::") {
+            for name in names.iter_mut() {
+                if name.starts_with("std::") {
+                    *name = name[5..].to_owned();
                 }
             }
+        }
 
-            let pair = {
-                // Try direct match
-                get_pair!("SELECT name,url FROM \"{}\" \
-                          WHERE name='{}' ORDER BY LENGTH(name)").or(
-                    // Try standard library
-                    get_pair!("SELECT name,url FROM \"{}\" \
-                              WHERE name=\"std::{}\" ORDER BY LENGTH(name)").or(
-                        get_pair!("SELECT name,url FROM \"{}\" \
-                                  WHERE name LIKE \"%{}%\" ORDER BY LENGTH(name)"))).map_err(|_| {
-                            io::Error::new(
-                                io::ErrorKind::NotFound,
-                                format!("No manual entry for {}", pattern))
-                        })
-            }?;
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Run `f` on the index database connection, opening it on first use and
+    /// keeping it open for later queries.
+    fn with_index<T, F>(&self, f: F) -> errors::Result<T>
+            where F: FnOnce(&Connection) -> errors::Result<T> {
+        if self.index_conn.borrow().is_none() {
+            if !self.env.index_db.exists() {
+                return Err(errors::ErrorKind::NoIndexDb.into());
+            }
 
-            page_name = pair.0;
-            url = pair.1;
+            // Migrating writes to the database, which may not be ours to
+            // change: one we can't write to is only read, if it's current
+            let conn = if is_writable(&self.env.index_db) {
+                let conn = Connection::open(&self.env.index_db)?;
+                index::upgrade(&conn)?;
+                conn
+            } else {
+                let conn = Connection::open_with_flags(&self.env.index_db, SQLITE_OPEN_READ_ONLY)?;
+                index::check_current(&conn)?;
+                conn
+            };
+            *self.index_conn.borrow_mut() = Some(conn);
         }
 
-        let page_filename = get_normalized_page_name(&page_name);
-        if self.forced || !avail.contains(&(page_filename + ".3.gz").into()) {
-            self.cache_man_page(&self.env.source.to_string(), &url, &page_name);
+        match *self.index_conn.borrow() {
+            Some(ref conn) => f(conn),
+            None           => Err(errors::ErrorKind::NoDbConn.into()),
         }
+    }
 
-        let pager_type = if stdout_isatty() { self.env.pager.to_string() } else { "pipe".to_owned() };
+    /// Where pages are fetched from, set up from the settings on first use.
+    fn transport(&self) -> errors::Result<Rc<Transport>> {
+        if self.transport.borrow().is_none() {
+            *self.transport.borrow_mut() = Some(transport::from_mode(&self.env.fetch, &self.http_config())?);
+        }
+
+        match *self.transport.borrow() {
+            Some(ref transport) => Ok(transport.clone()),
+            None                => unreachable!(),
+        }
+    }
+
+    /// Network settings of the transports.
+    fn http_config(&self) -> HttpConfig {
+        HttpConfig { quiet: self.quiet, ..HttpConfig::from_config(&self.env.config) }
+    }
+
+    /// Render a page to plain text `width` columns wide, caching it first if
+    /// needed.
+    pub fn render(&self, name: &str, url: &str, width: usize) -> errors::Result<String> {
+        let page_path = self.cached_page(name, url)?;
+        let groff_text = read_gz_file(&page_path)?;
+        groff2man_charset(groff_text.as_bytes(), width, self.env.config.charset())
+    }
+
+    /// Describe an index entry together with its cache status.
+    pub fn entry(&self, name: &str, url: &str) -> PageEntry {
+        let source = self.env.source.to_string();
+        let path = self.get_page_path(&source, name);
+
+        PageEntry {
+            name: name.to_owned(),
+            url: url.to_owned(),
+            cached: path.exists(),
+            cached_path: path.to_string_lossy().into_owned(),
+            source: source,
+        }
+    }
+
+    /// Call viewer.sh to view man page
+    pub fn man(&self, pattern: &str) -> errors::Result<()> {
+        let (page_name, url) = self.lookup(pattern)?;
+
+        let page_path = self.cached_page(&page_name, &url)?;
 
         // Call viewer
-        let columns = self.force_columns.or(Some(get_width()?))
-            .ok_or(errors::ErrorKind::Abort(
-                "Cannot determine width: either use --force-columns or switch to tty".to_owned()))?;
+        let columns = match self.force_columns.or(self.env.config.default_width()) {
+            Some(columns) => columns,
+            None          => get_width().map_err(|_| errors::ErrorKind::Abort(
+                "Cannot determine width: either use --force-columns, set DefaultWidth \
+                 or switch to tty".to_owned()))?,
+        };
+
+        // Editors and other programs reading our output get plain text
+        if !stdout_isatty() {
+            let groff_text = self.page_groff(&page_name, &page_path)?;
+            print!("{}", groff2man_charset(groff_text.as_bytes(), columns, self.env.config.charset())?);
+            return Ok(());
+        }
+
+        // The pager reads the selected sections from a page of their own
+        let page_path = match self.sections {
+            Some(_) => {
+                let path = self.env.cache_dir.join("sections").join(page_path.file_name().unwrap());
+                fs::create_dir_all(path.parent().unwrap())?;
+                write_gz_file(&path, &self.page_groff(&page_name, &page_path)?)?;
+                path
+            },
+            None    => page_path,
+        };
+
+        let pager_type = self.env.pager.to_string();
+        // Files found outside the data directory are installed, ours are
+        // kept up to date
+        if self.env.pager_script.starts_with(&self.env.data_dir) {
+            write_if_changed(&self.env.pager_script, PAGER_SCRIPT)?;
+        }
+        if let Pager::Vim = self.env.pager {
+            if self.env.pager_config.starts_with(&self.env.data_dir) {
+                ensure_pager_config(&self.env.pager_config)?;
+            }
+        }
 
+        // The pager script renders with the groff device in the environment
         Command::new("/bin/sh")
+                .env("CPPMAN_RS_GROFF_DEVICE", groff_device(self.env.config.charset()))
                 .arg(&self.env.pager_script)
                 .arg(pager_type)
-                .arg(self.get_page_path(&self.env.source.to_string(), &page_name))
+                .arg(page_path)
                 .arg(columns.to_string())
                 .arg(&self.env.pager_config)
                 .arg(page_name)
@@ -309,65 +925,104 @@ impl Cppman {
                 .map_err(Into::into)
     }
 
+    /// Groff text of the page of `name` stored at `page_path`, reduced to the
+    /// selected sections.
+    fn page_groff(&self, name: &str, page_path: &Path) -> errors::Result<String> {
+        let groff_text = read_gz_file(page_path)?;
+        match self.sections {
+            Some(ref sections) => select_sections(&groff_text, sections).ok_or_else(|| {
+                errors::Error::from(errors::ErrorKind::NoSection(name.to_owned(), sections.join(", ")))
+            }),
+            None               => Ok(groff_text),
+        }
+    }
+
     /// Find pages in database.
-    pub fn find(&self, pattern: &str) -> errors::Result<()> {
-        if !self.env.index_db.exists() {
-            return Err(errors::ErrorKind::NoIndexDb.into());
+    pub fn find(&self, pattern: &str, format: OutputFormat) -> errors::Result<()> {
+        let selected = self.search(pattern)?;
+        // Tools get an empty list
+        if selected.is_empty() && format == OutputFormat::Text {
+            return Err(errors::ErrorKind::NoMatch(pattern.to_owned()).into());
         }
 
-        let conn = Connection::open(&self.env.index_db)?;
-        let mut stmt = conn.prepare(&format!(
-            "SELECT * FROM \"{}\" WHERE name \
-             LIKE \"%{}%\" ORDER BY LENGTH(name)",
-            self.env.source, pattern))?;
-        let selected = stmt.query_and_then(&[], |&ref row| {
-            let a = row.get_checked(0)?;
-            let b = row.get_checked(1)?;
-            Ok((a, b))
-        })?.collect::<Result<Vec<(String, String)>, rusqlite::Error>>();
-
-        let pat = Regex::new(&format!("(?i)\\({}\\)", pattern))?;
-
-        if let Ok(sel) = selected {
-            if sel.len() > 0 {
-                for (name, url) in sel {
-                    if stdout_isatty() {
-                        println!("{}", pat.replace(&name, "\\033[1;31m$1\\033[0m"));
-                    } else {
-                        println!("{}", name);
-                    }
-                }
+        if format != OutputFormat::Text {
+            let mut writer = EntryWriter::new(format);
+            for (name, url) in selected {
+                writer.write(self.entry(&name, &url))?;
+            }
+            return writer.finish();
+        }
+
+        let pat = Regex::new(&format!("(?i)({})", regex::escape(pattern)))?;
+
+        let colour = match self.env.config.colour() {
+            Colour::Always => true,
+            Colour::Never  => false,
+            Colour::Auto   => stdout_isatty(),
+        };
 
-                return Ok(());
+        for (name, _) in selected {
+            if colour {
+                println!("{}", pat.replace_all(&name, "\x1b[1;31m$1\x1b[0m"));
+            } else {
+                println!("{}", name);
             }
         }
 
-        Err(errors::ErrorKind::NoMatch(pattern.to_owned()).into())
+        Ok(())
+    }
+
+    /// Print the index entries that `view` would open for each pattern. The
+    /// patterns that match nothing are reported among them, failing at the
+    /// end.
+    pub fn lookup_all<'a, I>(&self, patterns: I, format: OutputFormat) -> errors::Result<()>
+            where I: IntoIterator<Item = &'a str> {
+        let mut writer = EntryWriter::new(format);
+        let mut unresolved = 0;
+        for pattern in patterns {
+            match self.lookup(pattern) {
+                Ok((name, url)) => writer.write(self.entry(&name, &url))?,
+                Err(e)          => {
+                    // Others, such as a missing index, concern every pattern
+                    if e.exit_code() != errors::EXIT_NOT_FOUND {
+                        return Err(e);
+                    }
+                    writer.write_error(pattern, &e)?;
+                    unresolved += 1;
+                },
+            }
+        }
+        writer.finish()?;
+
+        match unresolved {
+            0 => Ok(()),
+            n => Err(errors::ErrorKind::Unresolved(n).into()),
+        }
     }
 
     /// Update mandb.
     fn update_mandb(&self, quiet: Option<bool>) -> errors::Result<()> {
-        let quiet = quiet.unwrap_or(true);
-
         if !self.env.config.update_man_path() {
             return Ok(());
         }
 
         println!("\nrunning mandb...");
-        let mut cmd = Command::new("mandb");
-
-        if quiet {
-            cmd.arg("-q");
-        }
-
-        cmd.status().map(|_| ()).map_err(Into::into)
+        mandb::update(&self.env, quiet.unwrap_or(true))
     }
 
     fn get_page_path(&self, source: &str, name: &str) -> PathBuf {
         let name = get_normalized_page_name(name);
         let mut path = PathBuf::from(&self.env.man_dir);
         path.push(source);
-        path.push(name + ".3.gz");
+        path.push(format!("{}.{}.gz", name, self.env.config.man_section()));
+        path
+    }
+
+    fn get_html_path(&self, source: &str, name: &str) -> PathBuf {
+        let name = get_normalized_page_name(name);
+        let mut path = self.env.cache_dir.join("html");
+        path.push(source);
+        path.push(name + ".html.gz");
         path
     }
 }
@@ -376,6 +1031,48 @@ fn get_normalized_page_name(name: &str) -> String {
     name.replace("/", "_")
 }
 
+/// Compare two groff pages, ignoring the `.TH` line which carries the
+/// generation date.
+fn same_groff(a: &str, b: &str) -> bool {
+    let strip = |s: &str| s.lines().filter(|l| !l.starts_with(".TH ")).collect::<Vec<_>>().join("\n");
+    strip(a) == strip(b)
+}
+
+
+/// Add `class` at the depth of `chain`, the classes it is a base of, then
+/// the bases in `bases_of` not also bases of another one of them.
+fn push_hierarchy(bases_of: &BTreeMap<String, Vec<String>>, class: &str,
+                  chain: &mut Vec<String>, rows: &mut Vec<(usize, String)>) {
+    rows.push((chain.len(), class.to_owned()));
+
+    let bases = match bases_of.get(class) {
+        Some(bases) => bases,
+        None        => return,
+    };
+
+    chain.push(class.to_owned());
+    for base in bases {
+        let indirect = bases.iter().any(|other| bases_of.get(other).map_or(false, |b| b.contains(base)));
+        if !indirect && !chain.contains(base) {
+            push_hierarchy(bases_of, base, chain, rows);
+        }
+    }
+    chain.pop();
+}
+
+
+/// Whether the SQLite database at `path` can be written to, with the journal
+/// next to it.
+fn is_writable(path: &Path) -> bool {
+    let writable = |path: &Path| fs::metadata(path).map(|m| !m.permissions().readonly()).unwrap_or(false);
+    let dir_writable = match path.parent() {
+        Some(dir) if dir != Path::new("") => writable(dir),
+        _                                 => true,
+    };
+
+    // Permissions are checked too, as they don't stop root
+    dir_writable && writable(path) && fs::OpenOptions::new().append(true).open(path).is_ok()
+}
 
 fn update_add_cell_op<T>(cell: &Cell<Option<T>>, value: T)
         where T: Copy + Default + AddAssign {
@@ -383,3 +1080,233 @@ fn update_add_cell_op<T>(cell: &Cell<Option<T>>, value: T)
                  .and(Some(Default::default()))
                  .map(|v: T| { let mut v = v; v.add_assign(value); v }));
 }
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Read;
+    use std::path::Path;
+
+    use rusqlite::Connection;
+    use url::Url;
+
+    use super::Cppman;
+    use ::config::Source;
+    use ::environ::{Environ, Paths};
+    use ::errors::ErrorKind;
+    use ::index::{self, Kind, PageInfo};
+    use ::output::OutputFormat;
+    use ::transport::{save, FetchMode, Fetched};
+    use ::util::{read_gz_file, write_gz_file};
+
+    const PRINTF: &'static str = "\
+        <html><body><div class=\"C_doc\">\
+        <div id=\"I_type\">function</div>\n<div id=\"I_file\">&lt;cstdio&gt;</div>\n\
+        <h1>printf</h1>\n<div class=\"C_prototype\"><pre>int printf ( const char * format, ... );</pre></div>\n\
+        <div id=\"I_description\">Print formatted data to stdout</div>\n\
+        <h3>Parameters</h3><dl><dt>format</dt><dd>C string that contains the text.</dd></dl>\n\
+        <div id=\"CH_bb\"></div></div></body></html>";
+
+    fn record(dir: &Path, url: &str, body: &str) {
+        let url = Url::parse(url).unwrap();
+        save(dir, &url, &Fetched {
+            url: url.clone(),
+            redirects: Vec::new(),
+            status: 200,
+            content_type: Some("text/html".to_owned()),
+            body: body.as_bytes().to_vec(),
+        }).unwrap();
+    }
+
+    fn replaying_environ(root: &Path) -> Environ {
+        let mut env = Environ::from_paths(Paths::under(root)).unwrap();
+        env.fetch = FetchMode::Replay(root.join("recorded"));
+        env
+    }
+
+    #[test]
+    fn test_extract_name() {
+        let root = env::temp_dir().join("cppman-rs-test-extract-name");
+        let env = Environ::from_paths(Paths::under(&root)).unwrap();
+        let cm = Cppman::new_default(&env);
+        assert_eq!(cm.extract_name("<h1>std::<b>operator&lt;&lt;</b> &amp; &ge;</h1>").unwrap(),
+                   "std::operator<< & \u{2265}");
+    }
+
+    /// Index and cache a recorded site without touching the network.
+    #[test]
+    fn test_replay_rebuild_and_cache() {
+        let root = env::temp_dir().join("cppman-rs-test-replay");
+        let _ = fs::remove_dir_all(&root);
+        let recorded = root.join("recorded");
+        fs::create_dir_all(&recorded).unwrap();
+
+        record(&recorded, "http://www.cplusplus.com/reference/",
+               "<h1>Reference</h1><a href=\"/reference/cstdio/printf/\">printf</a>\
+                <a href=\"http://example.com/\">elsewhere</a>");
+        record(&recorded, "http://www.cplusplus.com/reference/cstdio/printf/", PRINTF);
+
+        Cppman::new_default(&replaying_environ(&root)).rebuild_index(false).unwrap();
+
+        // Pick up the rebuilt index
+        let cm = Cppman::new_default(&replaying_environ(&root));
+        let (name, url) = cm.lookup("printf").unwrap();
+        assert_eq!(name, "printf");
+        assert_eq!(url, "http://www.cplusplus.com/reference/cstdio/printf/");
+
+        cm.cache_man_page("cplusplus.com", &url, &[name.clone()]).unwrap();
+        let groff_text = read_gz_file(cm.get_page_path("cplusplus.com", &name)).unwrap();
+        assert!(groff_text.contains(".TH \"printf\" 3 \""));
+        assert!(groff_text.contains(".SH \"NAME\"\nprintf \\- Print formatted data to stdout\n"));
+        assert!(groff_text.contains(".SH \"SYNOPSIS\"\n#include <cstdio>"));
+
+        assert_eq!(cm.which_header("std::printf").unwrap(), vec!["<cstdio>"]);
+        assert_eq!(cm.header_symbols("cstdio").unwrap(), vec![(Some(Kind::Function), vec!["printf".to_owned()])]);
+    }
+
+    fn contents(path: &Path) -> Vec<u8> {
+        let mut contents = Vec::new();
+        fs::File::open(path).unwrap().read_to_end(&mut contents).unwrap();
+        contents
+    }
+
+    fn set_readonly(path: &Path, readonly: bool) {
+        let mut permissions = fs::metadata(path).unwrap().permissions();
+        permissions.set_readonly(readonly);
+        fs::set_permissions(path, permissions).unwrap();
+    }
+
+    /// An index that can't be written to is used as it is if it's current,
+    /// and left alone otherwise.
+    #[test]
+    fn test_read_only_index() {
+        let root = env::temp_dir().join("cppman-rs-test-read-only-index");
+        let _ = fs::remove_dir_all(&root);
+        let paths = Paths::under(&root);
+        fs::create_dir_all(&paths.data_dir).unwrap();
+
+        let index_db = paths.data_dir.join("index.db");
+        Connection::open(&index_db).unwrap().execute_batch("
+            CREATE TABLE \"cplusplus.com\" (name VARCHAR(255), url VARCHAR(255));
+            INSERT INTO \"cplusplus.com\" VALUES ('printf', 'u1');").unwrap();
+        let before = contents(&index_db);
+
+        set_readonly(&index_db, true);
+        let env = Environ::from_paths(paths.clone()).unwrap();
+        match Cppman::new_default(&env).lookup("printf") {
+            Err(ref e) => match *e.kind() {
+                ErrorKind::IndexNotMigrated(2, _) => {},
+                _                                 => panic!("unexpected error: {}", e),
+            },
+            Ok(_)      => panic!("an outdated read-only index was used"),
+        }
+        assert_eq!(contents(&index_db), before);
+
+        set_readonly(&index_db, false);
+        let env = Environ::from_paths(paths).unwrap();
+        assert_eq!(Cppman::new_default(&env).lookup("printf").unwrap(), ("printf".to_owned(), "u1".to_owned()));
+        assert!(contents(&index_db) != before);
+
+        // Now current, it can be read without writing
+        set_readonly(&index_db, true);
+        let env = Environ::from_paths(Paths::under(&root)).unwrap();
+        assert_eq!(Cppman::new_default(&env).lookup("printf").unwrap(), ("printf".to_owned(), "u1".to_owned()));
+        set_readonly(&index_db, false);
+    }
+
+    /// Class pages are cached and read without a writable index, their
+    /// members being read from the page each time.
+    #[test]
+    fn test_class_read_only_index() {
+        let root = env::temp_dir().join("cppman-rs-test-class-read-only-index");
+        let _ = fs::remove_dir_all(&root);
+        let recorded = root.join("recorded");
+        fs::create_dir_all(&recorded).unwrap();
+        let url = "http://www.cplusplus.com/reference/istream/istream/";
+        record(&recorded, url,
+               "<html><body><div class=\"C_doc\"><div id=\"I_type\">class</div>\n\
+                <div id=\"I_file\">&lt;istream&gt;</div>\n<h1>std::istream</h1>\n\
+                <div id=\"I_description\">Input stream</div>\n\
+                <h3>Public member functions</h3>\n\
+                <dl class=\"links\"><dt><a href=\"/istream/gcount/\"><b>gcount</b></a></dt>\
+                <dd>Get character count <span class=\"typ\">(public member function)</span></dd></dl>\n\
+                <h3>Public member functions inherited from ios</h3>\n\
+                <dl class=\"links\"><dt><a href=\"/ios/good/\"><b>good</b></a></dt>\
+                <dd>Check whether state of stream is good <span class=\"typ\">(public member function)</span></dd></dl>\n\
+                <div id=\"CH_bb\"></div></div></body></html>");
+
+        let paths = Paths::under(&root);
+        fs::create_dir_all(&paths.data_dir).unwrap();
+        let index_db = paths.data_dir.join("index.db");
+        {
+            let conn = Connection::open(&index_db).unwrap();
+            index::upgrade(&conn).unwrap();
+            index::insert(&conn, Source::CPlusPlus, "std::istream", url, &PageInfo::default()).unwrap();
+        }
+        let before = contents(&index_db);
+
+        set_readonly(&index_db, true);
+        let cm = Cppman::new_default(&replaying_environ(&root));
+        let (name, info) = cm.class_info("std::istream").unwrap();
+        assert_eq!(name, "std::istream");
+        assert_eq!(info.bases, vec!["ios"]);
+        assert!(cm.get_page_path("cplusplus.com", "std::istream").exists());
+        assert_eq!(cm.class_info("std::istream").unwrap().1, info);
+        assert_eq!(contents(&index_db), before);
+        set_readonly(&index_db, false);
+    }
+
+    /// Tools get every result, unresolved names and empty searches included.
+    #[test]
+    fn test_machine_output() {
+        let root = env::temp_dir().join("cppman-rs-test-machine-output");
+        let _ = fs::remove_dir_all(&root);
+        let paths = Paths::under(&root);
+        fs::create_dir_all(&paths.data_dir).unwrap();
+        {
+            let conn = Connection::open(paths.data_dir.join("index.db")).unwrap();
+            index::upgrade(&conn).unwrap();
+            index::insert(&conn, Source::CPlusPlus, "printf", "u1", &PageInfo::default()).unwrap();
+        }
+
+        let cm = Cppman::new_default(&Environ::from_paths(paths).unwrap());
+        cm.find("nothing", OutputFormat::Json).unwrap();
+        assert!(cm.find("nothing", OutputFormat::Text).is_err());
+
+        match cm.lookup_all(vec!["nothing", "printf", "none"], OutputFormat::Ndjson) {
+            Err(ref e) => match *e.kind() {
+                ErrorKind::Unresolved(2) => {},
+                _                        => panic!("unexpected error: {}", e),
+            },
+            Ok(_)      => panic!("unresolved names were not reported"),
+        }
+    }
+
+    /// Pages are reformatted under their index names, not those of their
+    /// files.
+    #[test]
+    fn test_reformat_keeps_names() {
+        let root = env::temp_dir().join("cppman-rs-test-reformat");
+        let _ = fs::remove_dir_all(&root);
+        let paths = Paths::under(&root);
+        fs::create_dir_all(&paths.data_dir).unwrap();
+        {
+            let conn = Connection::open(paths.data_dir.join("index.db")).unwrap();
+            index::upgrade(&conn).unwrap();
+            for name in &["std::operator/", "operator/"] {
+                index::insert(&conn, Source::CPlusPlus, name, "u1", &PageInfo::default()).unwrap();
+            }
+        }
+
+        let cm = Cppman::new_default(&Environ::from_paths(paths).unwrap());
+        write_gz_file(cm.get_html_path("cplusplus.com", "std::operator/"), PRINTF).unwrap();
+        cm.reformat().unwrap();
+
+        let groff_text = read_gz_file(cm.get_page_path("cplusplus.com", "std::operator/")).unwrap();
+        assert!(groff_text.contains(".TH \"std::operator/\" 3 \""));
+        assert!(groff_text.contains(".SH \"NAME\"\nstd::operator/, operator/ \\- "));
+        assert!(cm.get_page_path("cplusplus.com", "operator/").exists());
+    }
+}
@@ -1,37 +1,43 @@
-use std::collections::HashSet;
-use std::io::Read;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+use std::thread;
+use std::time::Instant;
 
-use ordermap::OrderMap;
 use regex::Regex;
-use reqwest::{self, Client, Response, StatusCode, IntoUrl};
-use reqwest::header::{Headers, ContentType};
-use url::{self, Url};
+use reqwest::IntoUrl;
+use url::Url;
 
+use ::config::Config;
 use ::errors;
+use ::frontier::{CrawlState, Frontier};
+use ::http::{Http, HttpConfig};
+use ::robots::{parse_sitemap, Robots, Sitemap};
+use ::transport::{Fetched, Transport};
 
 
-pub struct Document<'a> {
+pub struct Document {
+    /// Canonical URL of the page, which it is indexed under.
     pub url: Url,
-    query: String,
-    status: StatusCode,
+    /// URL the page was served from, after redirects.
+    pub fetched_url: Url,
+    /// URLs that redirected to `fetched_url`, starting with the requested one.
+    pub redirects: Vec<Url>,
     pub text: String,
-    headers: &'a Headers,
 }
 
-impl<'a> Document<'a> {
-    pub fn new<T: IntoUrl>(response:&'a mut Response, url: T) -> Document<'a> {
-        let url =  url.into_url().unwrap();
+impl Document {
+    /// The URL named by `<link rel=canonical>` is used as the page's URL if
+    /// there is one.
+    pub fn new(fetched: Fetched) -> Document {
+        let text = fetched.text();
+        let url = canonical_url(&text, &fetched.url).unwrap_or(fetched.url.clone());
 
         Document {
-            url: url.clone(),
-            query: url.query().unwrap_or("").to_owned(),
-            status: *response.status(),
-            text: {
-                let mut text = String::new();
-                response.read_to_string(&mut text);
-                text
-            },
-            headers: response.headers(),
+            url: url,
+            fetched_url: fetched.url,
+            redirects: fetched.redirects,
+            text: text,
         }
     }
 }
@@ -39,78 +45,526 @@ impl<'a> Document<'a> {
 
 lazy_static! {
     static ref LINK: Regex = Regex::new("(?s)href\\s*=\\s*['\"]([^'\"]+)['\"]").unwrap();
+    static ref CANONICAL: Regex = Regex::new("(?is)<link\\s[^>]*\\brel\\s*=\\s*['\"]?canonical\\b[^>]*>").unwrap();
 }
 
-enum FollowMode {
+pub enum FollowMode {
     Any, SameDomain, SameHost, SamePath,
 }
 
+
+/// Why a page wasn't indexed.
+#[derive(Copy, Clone)]
+pub enum Skip {
+    Query, Unrelated, NotIncluded, Excluded, MaxDepth, MaxPages, Robots, Failed, NotHtml,
+    /// Another URL of a page indexed under its canonical URL.
+    Alias,
+}
+
+impl Into<&'static str> for Skip {
+    fn into(self) -> &'static str {
+        match self {
+            Skip::Query       => "URL with a query",
+            Skip::Unrelated   => "outside the crawled site",
+            Skip::NotIncluded => "not matching CrawlInclude",
+            Skip::Excluded    => "matching CrawlExclude",
+            Skip::MaxDepth    => "deeper than CrawlMaxDepth",
+            Skip::MaxPages    => "beyond CrawlMaxPages",
+            Skip::Robots      => "disallowed by robots.txt",
+            Skip::Failed      => "failed download or error response",
+            Skip::NotHtml     => "not HTML",
+            Skip::Alias       => "redirected or not canonical",
+        }
+    }
+}
+
+
+/// Bounds on what is crawled.
+#[derive(Clone, Default)]
+pub struct Limits {
+    pub max_pages: Option<usize>,
+    /// Number of links followed from the start page.
+    pub max_depth: Option<usize>,
+    /// Only URLs matching one of these are crawled, unless it's empty.
+    pub include: Vec<Regex>,
+    pub exclude: Vec<Regex>,
+}
+
+impl Limits {
+    pub fn from_config(config: &Config) -> Limits {
+        Limits {
+            max_pages: config.crawl_max_pages(),
+            max_depth: config.crawl_max_depth(),
+            include: config.crawl_include(),
+            exclude: config.crawl_exclude(),
+        }
+    }
+}
+
+
+/// Outcome of a crawl.
+pub struct Summary {
+    pub root: Url,
+    pub visited: usize,
+    /// Number of skipped pages by reason.
+    pub skipped: Vec<(String, usize)>,
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let skipped = self.skipped.iter().map(|&(_, n)| n).sum::<usize>();
+        write!(f, "Crawled {} pages from '{}', skipped {}", self.visited, self.root, skipped)?;
+        for &(ref reason, count) in &self.skipped {
+            write!(f, "\n  {:>6}  {}", count, reason)?;
+        }
+        Ok(())
+    }
+}
+
+
 pub struct Crawler {
-    visited: HashSet<Url>,
-    targets: OrderMap<Url, ()>,
+    frontier: Frontier,
+    limits: Limits,
+    follow_mode: FollowMode,
+    root: Option<Url>,
+    transport: Option<Rc<Transport>>,
+    /// `robots.txt` rules and time of the last request, by origin.
+    robots: HashMap<String, Robots>,
+    last_fetch: HashMap<String, Instant>,
 }
 
 impl Crawler {
+    /// A crawler keeping its state in memory.
     pub fn new() -> Crawler {
+        Crawler::with_frontier(Frontier::in_memory().expect("Failed to create in-memory crawl state"))
+    }
+
+    /// A crawler keeping its state in `frontier`, resuming the crawl it
+    /// holds if any.
+    pub fn with_frontier(frontier: Frontier) -> Crawler {
         Crawler {
-            visited: HashSet::new(),
-            targets: OrderMap::new(),
+            frontier: frontier,
+            limits: Limits::default(),
+            follow_mode: FollowMode::SamePath,
+            root: None,
+            transport: None,
+            robots: HashMap::new(),
+            last_fetch: HashMap::new(),
         }
     }
 
-    pub fn crawl<T: IntoUrl>(&mut self, url: T) -> errors::Result<()> {
-        self.add_target(url);
+    pub fn set_follow_mode(&mut self, mode: FollowMode) {
+        self.follow_mode = mode;
+    }
 
-        let client = Client::new()?;
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
 
-        while self.targets.len() > 0 {
-            let (url, _) = self.targets.pop().unwrap();
+    /// Fetch pages through `transport` instead of a client with default
+    /// settings.
+    pub fn set_transport(&mut self, transport: Rc<Transport>) {
+        self.transport = Some(transport);
+    }
+
+    /// Crawl pages reachable from `url`, calling `callback` for each of them.
+    ///
+    /// The site's `robots.txt` is obeyed, and pages listed in its sitemaps
+    /// are queued up front. An unfinished crawl from `url` in the frontier
+    /// is resumed, and a finished one isn't repeated.
+    pub fn crawl<T, F>(&mut self, url: T, mut callback: F) -> errors::Result<Summary>
+            where T: IntoUrl, F: FnMut(&Document) {
+        let url = url.into_url()?;
+        self.root = Some(url.clone());
+        let root = url.to_string();
 
-            let mut res = client.get(url.clone()).send()?;
-            if !res.status().is_success() {
-                let ct = res.headers().get::<ContentType>();
-                if !ct.is_some() || equal_content_types(ct.unwrap(), &ContentType(mime!(Text/Html))) {
+        let transport: Rc<Transport> = match self.transport {
+            Some(ref transport) => transport.clone(),
+            None                => Rc::new(Http::new(&HttpConfig::default())?),
+        };
+
+        match self.frontier.begin(&root)? {
+            CrawlState::New        => {
+                self.add_target(&url, 0)?;
+                self.seed_from_sitemaps(&*transport, &url)?;
+            },
+            CrawlState::InProgress => println!("Resuming crawl of '{}' ...", url),
+            CrawlState::Done       => return self.summary(&root),
+        }
+
+        while let Some((url, depth)) = self.frontier.next(&root)? {
+            let full = match self.limits.max_pages {
+                Some(max) => self.frontier.visited_count(&root)? >= max,
+                None      => false,
+            };
+            if full {
+                self.skip(&url, Skip::MaxPages)?;
+                continue;
+            }
+
+            if !self.is_allowed(&*transport, &url)? {
+                println!("Skipping '{}': disallowed by robots.txt", url);
+                self.skip(&url, Skip::Robots)?;
+                continue;
+            }
+
+            // A page that can't be fetched, e.g. for a redirect loop, doesn't
+            // stop the crawl
+            let fetched = match self.fetch(&*transport, &url) {
+                Ok(fetched) => fetched,
+                Err(e)      => {
+                    println!("Skipping '{}': {}", url, e);
+                    self.skip(&url, Skip::Failed)?;
                     continue;
+                },
+            };
+            if !fetched.is_success() {
+                self.skip(&url, Skip::Failed)?;
+                continue;
+            }
+            if !fetched.is_html() {
+                self.skip(&url, Skip::NotHtml)?;
+                continue;
+            }
+            if !self.should_follow(&fetched.url) {
+                self.skip(&url, Skip::Unrelated)?;
+                continue;
+            }
+
+            let mut doc = Document::new(fetched);
+            doc.url.set_fragment(None);
+            if !self.should_follow(&doc.url) {
+                doc.url = doc.fetched_url.clone();
+            }
+            if !doc.redirects.is_empty() {
+                println!("Followed redirects {} -> '{}'", doc.redirects.iter()
+                    .map(|u| format!("'{}'", u)).collect::<Vec<_>>().join(" -> "), doc.fetched_url);
+            }
+
+            // Other URLs of the page are never fetched again, and neither is
+            // the page if it was indexed under its canonical URL already
+            let indexed = self.frontier.is_visited(&root, &doc.url)?;
+            for alias in Some(&url).into_iter().chain(&doc.redirects).chain(Some(&doc.fetched_url)) {
+                if *alias != doc.url {
+                    self.skip(alias, Skip::Alias)?;
                 }
             }
 
-            let mut text = String::new();
-            res.read_to_string(&mut text);
+            // Marked before the callback, so that a page breaking it isn't
+            // fetched again on every resume
+            self.frontier.mark_visited(&root, &doc.url)?;
+            if indexed {
+                continue;
+            }
 
-            self.visited.insert(url);
+            callback(&doc);
 
-            for cap in LINK.captures_iter(&text) {
-                self.add_target(&cap[1]);
+            for cap in LINK.captures_iter(&doc.text) {
+                if let Ok(link) = doc.fetched_url.join(&cap[1]) {
+                    self.add_target(&link, depth + 1)?;
+                }
             }
         }
 
-        Ok(())
+        self.frontier.finish(&root)?;
+        self.summary(&root)
     }
 
-    fn add_target<T: IntoUrl>(&mut self, url: T) -> Result<(), url::ParseError> {
-        let url = url.into_url()?;
+    fn summary(&self, root: &str) -> errors::Result<Summary> {
+        Ok(Summary {
+            root: Url::parse(root)?,
+            visited: self.frontier.visited_count(root)?,
+            skipped: self.frontier.skipped_counts(root)?,
+        })
+    }
+
+    fn skip(&self, url: &Url, reason: Skip) -> errors::Result<()> {
+        let root = self.root.as_ref().map_or(String::new(), Url::to_string);
+        self.frontier.mark_skipped(&root, url, reason.into())
+    }
+
+    /// Fetch `url`, waiting for the crawl delay of its site first.
+    fn fetch(&mut self, transport: &Transport, url: &Url) -> errors::Result<Fetched> {
+        let origin = url.origin().ascii_serialization();
+        let delay = self.robots.get(&origin).and_then(Robots::crawl_delay);
+
+        if let (Some(delay), Some(last)) = (delay, self.last_fetch.get(&origin)) {
+            let elapsed = last.elapsed();
+            if elapsed < delay {
+                thread::sleep(delay - elapsed);
+            }
+        }
+
+        let fetched = transport.fetch(url);
+        self.last_fetch.insert(origin, Instant::now());
+        fetched
+    }
+
+    /// The `robots.txt` rules for the site of `url`, fetched on first use.
+    fn robots(&mut self, transport: &Transport, url: &Url) -> errors::Result<&Robots> {
+        let origin = url.origin().ascii_serialization();
+
+        if !self.robots.contains_key(&origin) {
+            let robots_url = url.join("/robots.txt")?;
+            let robots = match self.fetch(transport, &robots_url) {
+                Ok(ref f) if f.is_success()                    => Robots::parse(&f.text()),
+                // Access to the rules themselves is forbidden
+                Ok(ref f) if f.status == 401 || f.status == 403 => Robots::disallow_all(),
+                Ok(_)                                          => Robots::allow_all(),
+                Err(e)                                         => {
+                    println!("Couldn't fetch '{}': {}", robots_url, e);
+                    Robots::allow_all()
+                },
+            };
+            self.robots.insert(origin.clone(), robots);
+        }
+
+        Ok(&self.robots[&origin])
+    }
+
+    fn is_allowed(&mut self, transport: &Transport, url: &Url) -> errors::Result<bool> {
+        Ok(self.robots(transport, url)?.is_allowed(url))
+    }
+
+    /// Queue the pages listed in the sitemaps of the site of `root`: those
+    /// named in `robots.txt`, or `/sitemap.xml`.
+    fn seed_from_sitemaps(&mut self, transport: &Transport, root: &Url) -> errors::Result<()> {
+        let mut sitemaps = self.robots(transport, root)?.sitemaps().iter()
+            .filter_map(|s| Url::parse(s).ok())
+            .collect::<Vec<_>>();
+        if sitemaps.is_empty() {
+            sitemaps.push(root.join("/sitemap.xml")?);
+        }
+
+        let mut seen = HashSet::new();
+        while let Some(sitemap) = sitemaps.pop() {
+            if !seen.insert(sitemap.clone()) || !self.is_allowed(transport, &sitemap)? {
+                continue;
+            }
+
+            let fetched = match self.fetch(transport, &sitemap) {
+                Ok(ref f) if f.is_success() => parse_sitemap(&f.body),
+                _                           => continue,
+            };
 
-        if !self.visited.contains(&url) {
-            self.targets.insert(url, ());
+            match fetched {
+                Sitemap::Pages(pages) => {
+                    for page in pages.iter().filter_map(|p| Url::parse(p).ok()) {
+                        self.add_target(&page, 1)?;
+                    }
+                },
+                Sitemap::Index(maps)  => sitemaps.extend(maps.iter().filter_map(|m| Url::parse(m).ok())),
+            }
         }
 
         Ok(())
     }
+
+    /// Queue `url`, found `depth` links away from the start page, unless
+    /// the limits exclude it.
+    fn add_target(&mut self, url: &Url, depth: usize) -> errors::Result<()> {
+        let mut url = url.clone();
+        url.set_fragment(None);
+
+        let skip = if url.query().is_some() {
+            Some(Skip::Query)
+        } else if !self.should_follow(&url) {
+            Some(Skip::Unrelated)
+        } else if !self.limits.include.is_empty() && !self.limits.include.iter().any(|r| r.is_match(url.as_str())) {
+            Some(Skip::NotIncluded)
+        } else if self.limits.exclude.iter().any(|r| r.is_match(url.as_str())) {
+            Some(Skip::Excluded)
+        } else if self.limits.max_depth.map_or(false, |max| depth > max) {
+            Some(Skip::MaxDepth)
+        } else {
+            None
+        };
+
+        match skip {
+            Some(reason) => self.skip(&url, reason),
+            None         => {
+                let root = self.root.as_ref().map_or(String::new(), Url::to_string);
+                self.frontier.push(&root, &url, depth)
+            },
+        }
+    }
+
+    /// Check whether `url` is related to the crawl root as the follow mode
+    /// demands.
+    fn should_follow(&self, url: &Url) -> bool {
+        let root = match self.root {
+            Some(ref root) => root,
+            None           => return true,
+        };
+
+        match self.follow_mode {
+            FollowMode::Any        => true,
+            FollowMode::SameDomain => {
+                match (root.domain(), url.domain()) {
+                    (Some(r), Some(u)) => base_domain(r) == base_domain(u),
+                    _                  => false,
+                }
+            },
+            FollowMode::SameHost   => root.host_str() == url.host_str(),
+            FollowMode::SamePath   => {
+                root.host_str() == url.host_str() && url.path().starts_with(root.path())
+            },
+        }
+    }
 }
 
-fn equal_content_types(ct1: &ContentType, ct2: &ContentType) -> bool {
-    ct1.0 == ct2.0
+/// Strip the leftmost label, e.g. `www.cplusplus.com` becomes `cplusplus.com`.
+fn base_domain(domain: &str) -> &str {
+    match domain.find('.') {
+        Some(pos) if domain[pos+1..].contains('.') => &domain[pos+1..],
+        _                                          => domain,
+    }
+}
+
+/// The URL named by a `<link rel=canonical>` in `html`, relative to `base`.
+fn canonical_url(html: &str, base: &Url) -> Option<Url> {
+    CANONICAL.find(html)
+        .and_then(|tag| LINK.captures(tag.as_str()))
+        .and_then(|cap| base.join(&cap[1]).ok())
 }
 
 
 #[cfg(test)]
 mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+
+    use regex::Regex;
+    use url::Url;
+
+    use super::{Crawler, Limits};
+    use ::errors;
+    use ::transport::{save, Fetched, ReplayTransport, Transport};
+
+    fn record(dir: &Path, path: &str, fetched_path: &str, content_type: &str, body: &str) {
+        let base = Url::parse("http://www.cplusplus.com/reference/").unwrap();
+        let url = base.join(path).unwrap();
+        let fetched_url = base.join(fetched_path).unwrap();
+        save(dir, &url, &Fetched {
+            redirects: if url == fetched_url { Vec::new() } else { vec![url.clone()] },
+            url: fetched_url,
+            status: 200,
+            content_type: Some(content_type.to_owned()),
+            body: body.as_bytes().to_vec(),
+        }).unwrap();
+    }
+
+    fn replaying_crawler(name: &str) -> (Crawler, PathBuf) {
+        let dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut crawler = Crawler::new();
+        crawler.set_transport(Rc::new(ReplayTransport::new(&dir)));
+        (crawler, dir)
+    }
+
+    fn sorted(mut skipped: Vec<(String, usize)>) -> Vec<(String, usize)> {
+        skipped.sort();
+        skipped
+    }
+
     #[test]
     fn test_crawl() {
-        let url = "http://cplusplus.com/reference/";
-        let crawler = ::crawler::Crawler::new();
-        crawler.crawl(url);
-        println!("{:?}", crawler.visited);
-        println!("\n-------------------------------\n{:?}", crawler.targets);
+        let (mut crawler, dir) = replaying_crawler("cppman-rs-test-crawl");
+        record(&dir, "", "", "text/html", "<a href=\"a/\">a</a><a href=\"b/\">b</a><a href=\"c/\">c</a>");
+        for path in &["a/", "b/", "c/"] {
+            record(&dir, path, path, "text/html", "");
+        }
+        crawler.set_limits(Limits { max_pages: Some(3), ..Limits::default() });
+
+        let mut crawled = Vec::new();
+        let summary = crawler.crawl("http://www.cplusplus.com/reference/", |doc| {
+            crawled.push(doc.url.path().to_owned())
+        }).unwrap();
+
+        assert_eq!(crawled, vec!["/reference/", "/reference/a/", "/reference/b/"]);
+        assert_eq!(summary.visited, 3);
+        assert_eq!(summary.to_string(), "Crawled 3 pages from 'http://www.cplusplus.com/reference/', skipped 1\n\
+                                         \x20      1  beyond CrawlMaxPages");
+    }
+
+    #[test]
+    fn test_limits() {
+        let (mut crawler, dir) = replaying_crawler("cppman-rs-test-crawl-limits");
+        record(&dir, "", "", "text/html",
+               "<a href=\"a/\">a</a><a href=\"old/\">old</a><a href=\"?kw=a\">search</a>");
+        record(&dir, "a/", "a/", "text/html", "<a href=\"/reference/a/b/\">b</a>");
+        record(&dir, "a/b/", "a/b/", "text/html", "");
+        record(&dir, "old/", "old/", "text/html", "");
+
+        crawler.set_limits(Limits {
+            max_depth: Some(1),
+            exclude: vec![Regex::new("/old/").unwrap()],
+            ..Limits::default()
+        });
+
+        let mut crawled = Vec::new();
+        let summary = crawler.crawl("http://www.cplusplus.com/reference/", |doc| {
+            crawled.push(doc.url.path().to_owned())
+        }).unwrap();
+
+        assert_eq!(crawled, vec!["/reference/", "/reference/a/"]);
+        assert_eq!(summary.visited, 2);
+        assert_eq!(sorted(summary.skipped), vec![("URL with a query".to_owned(), 1),
+                                                 ("deeper than CrawlMaxDepth".to_owned(), 1),
+                                                 ("matching CrawlExclude".to_owned(), 1)]);
+    }
+
+    #[test]
+    fn test_redirects_and_content_types() {
+        let (mut crawler, dir) = replaying_crawler("cppman-rs-test-crawl-aliases");
+        record(&dir, "", "", "text/html; charset=utf-8",
+               "<a href=\"x/\">x</a><a href=\"y/\">y</a><a href=\"z/\">z</a>\
+                <a href=\"logo.png\">logo</a><a href=\"missing/\">missing</a>");
+        record(&dir, "x/", "y/", "text/html", "<h1>y</h1>");
+        record(&dir, "z/", "z/", "text/html", "<link rel=\"canonical\" href=\"/reference/y/\"><h1>y</h1>");
+        record(&dir, "logo.png", "logo.png", "image/png", "<html>");
+
+        let mut crawled = Vec::new();
+        let summary = crawler.crawl("http://www.cplusplus.com/reference/", |doc| {
+            crawled.push((doc.url.path().to_owned(), doc.redirects.len()))
+        }).unwrap();
+
+        assert_eq!(crawled, vec![("/reference/".to_owned(), 0), ("/reference/y/".to_owned(), 1)]);
+        assert_eq!(sorted(summary.skipped), vec![("failed download or error response".to_owned(), 1),
+                                                 ("not HTML".to_owned(), 1),
+                                                 ("redirected or not canonical".to_owned(), 2)]);
+    }
+
+    /// Replays recorded pages, but fails on pages under `/loop/` as if they
+    /// redirected to each other.
+    struct LoopingTransport(ReplayTransport);
+
+    impl Transport for LoopingTransport {
+        fn fetch(&self, url: &Url) -> errors::Result<Fetched> {
+            if url.path().starts_with("/reference/loop/") {
+                return Err(errors::ErrorKind::TooManyRedirects(url.to_string()).into());
+            }
+            self.0.fetch(url)
+        }
+    }
+
+    #[test]
+    fn test_failed_download() {
+        let (mut crawler, dir) = replaying_crawler("cppman-rs-test-crawl-failed");
+        record(&dir, "", "", "text/html", "<a href=\"loop/\">loop</a><a href=\"a/\">a</a>");
+        record(&dir, "a/", "a/", "text/html", "<h1>a</h1>");
+        crawler.set_transport(Rc::new(LoopingTransport(ReplayTransport::new(&dir))));
+
+        let mut crawled = Vec::new();
+        let summary = crawler.crawl("http://www.cplusplus.com/reference/", |doc| {
+            crawled.push(doc.url.path().to_owned())
+        }).unwrap();
+
+        assert_eq!(crawled, vec!["/reference/", "/reference/a/"]);
+        assert_eq!(summary.skipped, vec![("failed download or error response".to_owned(), 1)]);
     }
 }
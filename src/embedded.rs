@@ -0,0 +1,63 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+
+use ::errors;
+
+
+/// Prebuilt index database, gzip-compressed, empty if none was embedded.
+static INDEX_DB_GZ: &'static [u8] = include_bytes!(concat!(env!("OUT_DIR"), "/index.db.gz"));
+
+/// Version of the embedded index, derived from its contents at build time.
+pub const INDEX_VERSION: &'static str = env!("CPPMAN_INDEX_VERSION");
+
+
+/// Whether this binary carries a prebuilt index.
+pub fn has_index() -> bool {
+    !INDEX_DB_GZ.is_empty()
+}
+
+/// Path of the version stamp written next to a materialised index.
+fn stamp_path(path: &Path) -> PathBuf {
+    path.with_extension("version")
+}
+
+/// Write the embedded index to `path`, unless a copy of the same version is
+/// already there.
+///
+/// Returns `false` if there is no embedded index.
+pub fn materialise_index(path: &Path) -> errors::Result<bool> {
+    if !has_index() {
+        return Ok(false);
+    }
+
+    let stamp = stamp_path(path);
+    let mut version = String::new();
+    if let Ok(mut file) = File::open(&stamp) {
+        file.read_to_string(&mut version)?;
+    }
+
+    if path.exists() && version.trim() == INDEX_VERSION {
+        return Ok(true);
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Decompress to a temporary file first so that an interrupted run never
+    // leaves a truncated database behind.
+    let tmp = path.with_extension("tmp");
+    {
+        let mut dec = GzDecoder::new(INDEX_DB_GZ)?;
+        let mut file = File::create(&tmp)?;
+        io::copy(&mut dec, &mut file)?;
+    }
+    fs::rename(&tmp, path)?;
+
+    File::create(&stamp)?.write_all(INDEX_VERSION.as_bytes())?;
+
+    Ok(true)
+}
@@ -1,21 +1,94 @@
 use std::env;
+use std::ffi::OsString;
 use std::fs;
-use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use ::get_lib_path;
 use ::config::{Config, Source, Pager};
+use ::embedded;
 use ::errors;
+use ::transport::FetchMode;
+
+
+/// Paths overriding the ones derived from the XDG base directories.
+///
+/// Each field is filled from a command line flag first, then from the
+/// corresponding `CPPMAN_*` environment variable.
+#[derive(Clone, Default)]
+pub struct Overrides {
+    pub config_dir: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    pub man_dir: Option<PathBuf>,
+}
+
+impl Overrides {
+    /// Fill unset fields from the `CPPMAN_CONFIG_DIR`, `CPPMAN_DATA_DIR`,
+    /// `CPPMAN_CACHE_DIR` and `CPPMAN_MAN_DIR` environment variables.
+    pub fn with_env(self) -> Overrides {
+        Overrides {
+            config_dir: self.config_dir.or(env_path("CPPMAN_CONFIG_DIR")),
+            data_dir: self.data_dir.or(env_path("CPPMAN_DATA_DIR")),
+            cache_dir: self.cache_dir.or(env_path("CPPMAN_CACHE_DIR")),
+            man_dir: self.man_dir.or(env_path("CPPMAN_MAN_DIR")),
+        }
+    }
+}
+
+
+/// Fully resolved locations used by an `Environ`.
+#[derive(Clone)]
+pub struct Paths {
+    pub home: PathBuf,
+    pub config_dir: PathBuf,
+    pub data_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub man_dir: PathBuf,
+}
+
+impl Paths {
+    /// Resolve locations from `$HOME` and the XDG base directories, applying
+    /// `overrides` on top.
+    pub fn resolve(overrides: Overrides) -> errors::Result<Paths> {
+        let home = env_path("HOME").ok_or(errors::ErrorKind::NoHomeDir)?;
+        let xdg_data_home = xdg_data_home(&home);
+
+        Ok(Paths {
+            config_dir: overrides.config_dir
+                .unwrap_or(xdg_dir("XDG_CONFIG_HOME", &home, ".config").join("cppman-rs")),
+            data_dir: overrides.data_dir.unwrap_or(xdg_data_home.join("cppman-rs")),
+            cache_dir: overrides.cache_dir
+                .unwrap_or(xdg_dir("XDG_CACHE_HOME", &home, ".cache").join("cppman-rs")),
+            man_dir: overrides.man_dir.unwrap_or(xdg_data_home.join("man")),
+            home: home,
+        })
+    }
+
+    /// Keep everything under `root`, without looking at the environment.
+    pub fn under<P: AsRef<Path>>(root: P) -> Paths {
+        let root = root.as_ref();
+
+        Paths {
+            home: root.to_owned(),
+            config_dir: root.join("config"),
+            data_dir: root.join("data"),
+            cache_dir: root.join("cache"),
+            man_dir: root.join("man"),
+        }
+    }
+}
 
 
 #[derive(Clone)]
 pub struct Environ {
-    home: PathBuf,
+    pub home: PathBuf,
     pub man_dir: PathBuf,
     config_dir: PathBuf,
     config_file: PathBuf,
     pub config: Config,
 
+    pub data_dir: PathBuf,
+    pub cache_dir: PathBuf,
+
     pub index_db_re: PathBuf,
     pub index_db: PathBuf,
 
@@ -24,6 +97,9 @@ pub struct Environ {
     pub pager_script: PathBuf,
 
     pub source: Source,
+
+    /// Set to record or replay downloads.
+    pub fetch: FetchMode,
 }
 
 impl Environ {
@@ -32,26 +108,59 @@ impl Environ {
     }
 
     pub fn try_new() -> errors::Result<Environ> {
-        let home = env::home_dir().unwrap();
+        Environ::with_overrides(Overrides::default().with_env())
+    }
 
-        let man_dir = home.join(".local/share/man/");
-        let config_dir = home.join(".config/cppman-rs/");
-        let config_file = config_dir.join("cppman-rs.cfg");
+    pub fn with_overrides(overrides: Overrides) -> errors::Result<Environ> {
+        // An explicit cache dir on the command line beats the config file
+        let explicit_cache_dir = overrides.cache_dir.is_some();
+        let mut paths = Paths::resolve(overrides)?;
+        let config = Config::new_try_from_file(paths.config_dir.join("cppman-rs.cfg"))?;
+
+        if !explicit_cache_dir {
+            apply_cache_dir(&mut paths, &config);
+        }
 
-        let config = Config::new_from_file(&config_file);
+        let resource_dirs = data_dirs(&paths.data_dir);
+        Environ::with_config(paths, config, resource_dirs)
+    }
+
+    /// Create an environment from explicit paths only.
+    ///
+    /// The config file is read from (or created in) `paths.config_dir`, and
+    /// its `CacheDir` replaces `paths.cache_dir` if set. Bundled resources are
+    /// only looked for in `paths.data_dir`, where the embedded index is
+    /// written if there is no other.
+    pub fn from_paths(mut paths: Paths) -> errors::Result<Environ> {
+        let config = Config::new_try_from_file(paths.config_dir.join("cppman-rs.cfg"))?;
+        apply_cache_dir(&mut paths, &config);
+        let resource_dirs = vec![paths.data_dir.clone()];
+        Environ::with_config(paths, config, resource_dirs)
+    }
+
+    /// Environment for `paths`, finding bundled resources in the first of
+    /// `resource_dirs` holding them.
+    fn with_config(paths: Paths, config: Config, resource_dirs: Vec<PathBuf>) -> errors::Result<Environ> {
+        let Paths { home, config_dir, data_dir, cache_dir, man_dir } = paths;
+        let config_file = config_dir.join("cppman-rs.cfg");
 
         fs::create_dir_all(&config_dir)?;
 
-        let index_db_re = config_dir.join("index.db");
+        let index_db_re = config.index_path().unwrap_or(data_dir.join("index.db"));
         let index_db = if index_db_re.exists() {
             index_db_re.clone()
+        } else if config_dir.join("index.db").exists() {
+            // Rebuilt by an older version that kept the index next to the config
+            config_dir.join("index.db")
+        } else if embedded::materialise_index(&data_dir.join("index.embedded.db"))? {
+            data_dir.join("index.embedded.db")
         } else {
-            get_lib_path("index.db")
+            find_data_file("index.db", &resource_dirs, &data_dir)
         };
 
         let pager = config.pager();
-        let pager_config = get_lib_path("cppman-rs.vim");
-        let pager_script = get_lib_path("pager.sh");
+        let pager_config = find_data_file("cppman-rs.vim", &resource_dirs, &data_dir);
+        let pager_script = find_data_file("pager.sh", &resource_dirs, &data_dir);
 
         let source = config.source();
 
@@ -61,12 +170,74 @@ impl Environ {
             config_dir: config_dir,
             config_file: config_file,
             config: config,
+            data_dir: data_dir,
+            cache_dir: cache_dir,
             index_db_re: index_db_re,
             index_db: index_db,
             pager: pager,
             pager_config: pager_config,
             pager_script: pager_script,
             source: source,
+            fetch: FetchMode::default(),
         })
     }
 }
+
+
+/// Use the `CacheDir` setting of `config` for `paths` if it is set.
+fn apply_cache_dir(paths: &mut Paths, config: &Config) {
+    if let Some(cache_dir) = config.cache_dir() {
+        paths.cache_dir = cache_dir;
+    }
+}
+
+/// Read a non-empty path from an environment variable.
+fn env_path(var: &str) -> Option<PathBuf> {
+    env::var_os(var).and_then(|v| if v.is_empty() { None } else { Some(PathBuf::from(v)) })
+}
+
+/// Resolve an XDG base directory, ignoring relative paths as the spec demands.
+fn xdg_dir(var: &str, home: &Path, fallback: &str) -> PathBuf {
+    match env_path(var) {
+        Some(ref dir) if dir.is_absolute() => dir.clone(),
+        _                                  => home.join(fallback),
+    }
+}
+
+/// `$XDG_DATA_HOME`, where other programs (man, Neovim) look for user data.
+pub fn xdg_data_home(home: &Path) -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", home, ".local/share")
+}
+
+/// Directories searched for bundled resources, most specific first: the
+/// executable's directory, `../share/cppman-rs` relative to it, the user data
+/// dir and the system data dirs from `XDG_DATA_DIRS`.
+fn data_dirs(data_dir: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    if let Some(exe_dir) = env::current_exe().ok().and_then(|e| e.parent().map(Path::to_owned)) {
+        let share_dir = exe_dir.join("../share/cppman-rs");
+        dirs.push(exe_dir);
+        dirs.push(share_dir);
+    }
+
+    dirs.push(data_dir.to_owned());
+
+    let system_dirs = env::var_os("XDG_DATA_DIRS")
+        .and_then(|v| if v.is_empty() { None } else { Some(v) })
+        .unwrap_or(OsString::from("/usr/local/share:/usr/share"));
+    dirs.extend(env::split_paths(&system_dirs)
+                    .filter(|d| d.is_absolute())
+                    .map(|d| d.join("cppman-rs")));
+
+    dirs
+}
+
+/// Find a bundled resource in `dirs`, falling back to the user data dir so
+/// that error messages point somewhere sensible.
+fn find_data_file(name: &str, dirs: &[PathBuf], data_dir: &Path) -> PathBuf {
+    dirs.iter()
+        .map(|d| d.join(name))
+        .find(|p| p.exists())
+        .unwrap_or(data_dir.join(name))
+}
@@ -8,7 +8,9 @@ error_chain! {
         Io(::std::io::Error);
         Regex(::regex::Error);
         Reqwest(::reqwest::Error);
+        Url(::url::ParseError);
         Rusqlite(::rusqlite::Error);
+        SerdeJson(::serde_json::Error);
     }
 
     errors {
@@ -22,6 +24,46 @@ error_chain! {
             display("cannot parse source from '{}'", input)
         }
 
+        ParseColour(input: String) {
+            description("cannot parse colour")
+            display("cannot parse colour from '{}'", input)
+        }
+
+        ParseCharset(input: String) {
+            description("cannot parse charset")
+            display("cannot parse charset from '{}'", input)
+        }
+
+        ParseStdVersion(input: String) {
+            description("cannot parse C++ standard version")
+            display("cannot parse C++ standard version from '{}'", input)
+        }
+
+        ParseManSection(input: String) {
+            description("cannot parse man section")
+            display("cannot parse man section from '{}'", input)
+        }
+
+        ParseKind(input: String) {
+            description("cannot parse symbol kind")
+            display("cannot parse symbol kind from '{}'", input)
+        }
+
+        ParseOutputFormat(input: String) {
+            description("cannot parse output format")
+            display("cannot parse output format from '{}'", input)
+        }
+
+        UnknownConfigKey(key: String, line: Option<usize>) {
+            description("unknown config key")
+            display("unknown config key '{}'{}", key, at_line(line))
+        }
+
+        InvalidConfigValue(key: String, line: Option<usize>, value: String, reason: String) {
+            description("invalid config value")
+            display("invalid value '{}' for config key '{}'{}: {}", value, key, at_line(line), reason)
+        }
+
         StdoutNoTermWidth {
             description("error while determining width of stdout terminal")
             display("error while determining width of stdout terminal")
@@ -37,11 +79,28 @@ error_chain! {
             display("no captures found at index {}", index)
         }
 
+        NoHomeDir {
+            description("can't determine home directory")
+            display("can't determine home directory, $HOME is not set")
+        }
+
         NoIndexDb {
             description("can't find index.db")
             display("can't find index.db")
         }
 
+        IndexMigrationFailed(version: i64, changes: String, msg: String) {
+            description("migrating the index database failed")
+            display("migrating the index database to schema version {} ({}) failed: {}; \
+                     run `cppman-rs index rebuild`", version, changes, msg)
+        }
+
+        IndexNotMigrated(version: i64, changes: String) {
+            description("the index database needs migrating")
+            display("the index database is read-only and needs migrating to schema version {} ({}); \
+                     run `cppman-rs index rebuild` or copy it to the data directory", version, changes)
+        }
+
         NoDbConn {
             description("no Cppman::db_conn available!")
             display("no Cppman::db_conn available!")
@@ -57,11 +116,76 @@ error_chain! {
             display("no match: '{}'", pattern)
         }
 
+        HttpStatus(url: String, status: String) {
+            description("HTTP request failed")
+            display("fetching '{}' failed: {}", url, status)
+        }
+
+        TooManyRedirects(url: String) {
+            description("too many redirects")
+            display("fetching '{}' failed: too many redirects", url)
+        }
+
+        NoManualEntry(pattern: String) {
+            description("no manual entry")
+            display("no manual entry for '{}'", pattern)
+        }
+
+        Unresolved(count: usize) {
+            description("names couldn't be resolved")
+            display("{} of the names couldn't be resolved", count)
+        }
+
+        NotAClass(name: String) {
+            description("not a class")
+            display("'{}' is not a class", name)
+        }
+
+        NoSection(name: String, sections: String) {
+            description("no such section")
+            display("the page of '{}' has no section {}", name, sections)
+        }
+
+        NoExample(name: String) {
+            description("no example")
+            display("the page of '{}' has no example", name)
+        }
+
+        CompileFailed(compiler: String, path: String) {
+            description("compiling an example failed")
+            display("compiling '{}' with '{}' failed", path, compiler)
+        }
+
+        ExampleFailed(program: String, status: String) {
+            description("running an example failed")
+            display("running '{}' failed ({})", program, status)
+        }
+
+        ExampleTimedOut(program: String, secs: u64) {
+            description("an example ran for too long")
+            display("'{}' was killed after running for {} seconds", program, secs)
+        }
+
+        ExampleOutputDiffers(name: String) {
+            description("example output differs")
+            display("the output of the example of '{}' differs from the documented output", name)
+        }
+
+        Usage(msg: String) {
+            description("usage error")
+            display("{}", msg)
+        }
+
         WrongSource(source: String) {
             description("wrong source")
             display("wrong source: '{}'", source)
         }
 
+        FormatterNotImplemented(source: String) {
+            description("formatter not implemented")
+            display("formatter not implemented for {}", source)
+        }
+
         Abort(msg: String) {
             description("critical error, must abort")
             display("{}", msg)
@@ -78,3 +202,47 @@ error_chain! {
         }
     }
 }
+
+
+/// Generic failure.
+pub const EXIT_FAILURE: i32 = 1;
+/// Invalid command line.
+pub const EXIT_USAGE: i32 = 2;
+/// No page or index entry matches the request.
+pub const EXIT_NOT_FOUND: i32 = 3;
+/// A page couldn't be downloaded.
+pub const EXIT_NETWORK: i32 = 4;
+/// There is no index database to search.
+pub const EXIT_NO_INDEX: i32 = 5;
+/// The configuration file or a setting is invalid.
+pub const EXIT_CONFIG: i32 = 6;
+
+impl Error {
+    /// Process exit code reported for this error.
+    pub fn exit_code(&self) -> i32 {
+        match *self.kind() {
+            ErrorKind::Usage(_) | ErrorKind::ParseOutputFormat(_) => EXIT_USAGE,
+            ErrorKind::NoMatch(_) |
+            ErrorKind::NoManualEntry(_) |
+            ErrorKind::Unresolved(_) |
+            ErrorKind::NotAClass(_) |
+            ErrorKind::NoSection(..) |
+            ErrorKind::NoExample(_) => EXIT_NOT_FOUND,
+            ErrorKind::Reqwest(_) |
+            ErrorKind::HttpStatus(..) |
+            ErrorKind::TooManyRedirects(_) => EXIT_NETWORK,
+            ErrorKind::NoIndexDb |
+            ErrorKind::IndexMigrationFailed(..) |
+            ErrorKind::IndexNotMigrated(..) => EXIT_NO_INDEX,
+            ErrorKind::Ini(_) |
+            ErrorKind::UnknownConfigKey(..) |
+            ErrorKind::InvalidConfigValue(..) => EXIT_CONFIG,
+            _ => EXIT_FAILURE,
+        }
+    }
+}
+
+
+fn at_line(line: &Option<usize>) -> String {
+    line.map_or(String::new(), |l| format!(" at line {}", l))
+}
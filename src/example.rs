@@ -0,0 +1,196 @@
+//! Examples at the end of pages, saved as source files and compiled with the
+//! local C++ compiler.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use ::config::StdVersion;
+use ::errors;
+
+
+/// Seconds an example program may run before it is killed.
+const RUN_TIMEOUT: u64 = 10;
+
+/// Words for the characters of operators in file names.
+const OPERATOR_CHARS: &'static [(char, &'static str)] = &[
+    ('!', "not"), ('"', "quote"), ('%', "percent"), ('&', "amp"), ('(', "lparen"), (')', "rparen"),
+    ('*', "star"), ('+', "plus"), (',', "comma"), ('-', "minus"), ('/', "slash"), ('<', "lt"),
+    ('=', "eq"), ('>', "gt"), ('[', "lbracket"), (']', "rbracket"), ('^', "caret"), ('|', "pipe"),
+    ('~', "tilde"),
+];
+
+
+lazy_static! {
+    static ref OPERATOR: Regex = Regex::new("\\boperator\\b").unwrap();
+}
+
+
+/// Example program of a page and the output documented with it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Example {
+    pub code: String,
+    pub output: Option<String>,
+}
+
+
+/// Name of the source file for the example of the page `name`, e.g.
+/// `vector_begin.cpp` for `vector::begin`. The characters of an operator are
+/// spelled out, `operator_plus_eq.cpp` for `operator+=`, to keep operators
+/// apart.
+pub fn file_name(name: &str) -> String {
+    let (name, operator) = match OPERATOR.find(name) {
+        Some(m) => name.split_at(m.end()),
+        None    => (name, ""),
+    };
+
+    let mut stem = String::new();
+    for c in name.chars() {
+        push_file_name_char(&mut stem, c);
+    }
+    for c in operator.chars() {
+        match OPERATOR_CHARS.iter().find(|&&(op, _)| op == c) {
+            Some(&(_, word)) => {
+                push_file_name_char(&mut stem, '_');
+                stem.push_str(word);
+            },
+            None => push_file_name_char(&mut stem, c),
+        }
+    }
+
+    format!("{}.cpp", stem.trim_matches('_'))
+}
+
+/// Push `c` to a file name, other characters than letters, digits and `_`
+/// as a single `_`.
+fn push_file_name_char(stem: &mut String, c: char) {
+    if c.is_alphanumeric() || c == '_' {
+        stem.push(c);
+    } else if !stem.ends_with('_') {
+        stem.push('_');
+    }
+}
+
+/// Write `example` to `dir` as the source file for the page `name`.
+pub fn save(example: &Example, dir: &Path, name: &str) -> errors::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(file_name(name));
+    File::create(&path)?.write_all(example.code.as_bytes())?;
+    Ok(path)
+}
+
+/// C++ compiler from `$CXX`, `c++` if unset.
+pub fn compiler() -> String {
+    match env::var("CXX") {
+        Ok(ref cxx) if !cxx.trim().is_empty() => cxx.clone(),
+        _                                     => "c++".to_owned(),
+    }
+}
+
+/// Compile the source file at `path` for `std_version` next to it, run the
+/// program without input and return what it prints on stdout. Diagnostics
+/// and the program's stderr go to our stderr. A program failing or running
+/// for longer than `RUN_TIMEOUT` seconds is an error, what it printed being
+/// written to our stdout first.
+pub fn compile_and_run(path: &Path, std_version: StdVersion) -> errors::Result<String> {
+    let cxx = compiler();
+    let program = path.with_extension("");
+    let std_flag = format!("-std={}", Into::<&'static str>::into(std_version));
+
+    let status = Command::new(&cxx)
+        .arg(&std_flag)
+        // Examples of <thread>, <future> and <mutex> need it with GCC
+        .arg("-pthread")
+        .arg("-o").arg(&program)
+        .arg(path)
+        .status()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => errors::Error::from(errors::ErrorKind::Abort(format!(
+                "cannot find the C++ compiler '{}', set $CXX to use another one", cxx))),
+            _ => errors::Error::from(e),
+        })?;
+    if !status.success() {
+        return Err(errors::ErrorKind::CompileFailed(cxx, path.to_string_lossy().into_owned()).into());
+    }
+
+    let mut child = Command::new(&program)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    // Read the output aside, not to block a program printing a lot
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+
+    // The exit status, `None` if the program had to be killed
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if started.elapsed() >= Duration::from_secs(RUN_TIMEOUT) {
+            child.kill()?;
+            child.wait()?;
+            break None;
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    let output = reader.join().expect("reading the output of the example panicked")?;
+    let output = String::from_utf8_lossy(&output).into_owned();
+    let program = program.to_string_lossy().into_owned();
+    match status {
+        Some(status) if status.success() => Ok(output),
+        Some(status) => {
+            print!("{}", output);
+            Err(errors::ErrorKind::ExampleFailed(program, status.to_string()).into())
+        },
+        None => {
+            print!("{}", output);
+            Err(errors::ErrorKind::ExampleTimedOut(program, RUN_TIMEOUT).into())
+        },
+    }
+}
+
+/// Whether a program printed `documented`, ignoring whitespace at the end of
+/// lines and of the output.
+pub fn same_output(actual: &str, documented: &str) -> bool {
+    let normalize = |s: &str| s.lines().map(str::trim_right).collect::<Vec<_>>().join("\n").trim_right().to_owned();
+    normalize(actual) == normalize(documented)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{file_name, same_output};
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("vector::begin"), "vector_begin.cpp");
+        assert_eq!(file_name("std::chrono::duration<Rep,Period>::count"),
+                   "std_chrono_duration_Rep_Period_count.cpp");
+
+        assert_eq!(file_name("operator+"), "operator_plus.cpp");
+        assert_eq!(file_name("operator-"), "operator_minus.cpp");
+        assert_eq!(file_name("std::operator<<"), "std_operator_lt_lt.cpp");
+        assert_eq!(file_name("vector::operator[]"), "vector_operator_lbracket_rbracket.cpp");
+        assert_eq!(file_name("operator new[]"), "operator_new_lbracket_rbracket.cpp");
+        assert_eq!(file_name("operator_traits::cooperator<T>"), "operator_traits_cooperator_T.cpp");
+    }
+
+    #[test]
+    fn test_same_output() {
+        assert!(same_output("myvector contains: 1 2 3\n", "myvector contains: 1 2 3 \n\n"));
+        assert!(!same_output("1 2 3\n", "1 2 4\n"));
+    }
+}
@@ -1,11 +1,16 @@
 use std::io::Read;
-use std::ops::Deref;
 
 use regex::{Regex, Captures};
 use reqwest;
 use chrono::Local;
 
+use ::config::StdVersion;
+use ::example::Example;
+use ::formatter::entities::decode_entities;
+use ::formatter::escape;
 use ::formatter::tableparser::parse_table;
+use ::formatter::utils::pre_text;
+use ::index::{ClassInfo, Kind, Member, PageInfo};
 use ::util::fixup_html;
 
 
@@ -17,8 +22,6 @@ lazy_static! {
 
     static ref PRE_SECTION: Regex = Regex::new("(?s)<pre.*?>(.*?)</pre>").unwrap();
     static ref TABLE: Regex = Regex::new("(?s)<table.*?>.*?</table>").unwrap();
-    static ref ESCAPE_COLUMN: (Regex, String) =
-        (Regex::new("(?s)T\\{\n(\\..*?)\nT\\}").unwrap(), "T{\n\\E $1\nT}".to_owned());
 
     static ref RPS: Vec<(Regex, String)> = vec![
         // Header, Name
@@ -26,7 +29,7 @@ lazy_static! {
                      <div id=\"I_file\"[^>]*>(.*?)</div>\\s*\
                      <h1>(.*?)</h1>\\s*<div class=\"C_prototype\"[^>]*>\
                      (.*?)</div>\\s*<div id=\"I_description\"[^>]*>(.*?)</div>").unwrap(),
-         format!(".TH \"$3\" 3 \"{}\" \"cplusplus.com\" \"C++ Programmer\\'s Manual\"\n\
+         format!(".TH \"$3\" 3 \"{}\" \"cplusplus.com\" \"C++ Programmer\\(aqs Manual\"\n\
                   \n.SH \"NAME\"\n$3 - $5\n\
                   \n.SE\n.SH \"TYPE\"\n$1\n\
                   \n.SE\n.SH \"SYNOPSIS\"\n#include $2\n.sp\n$4\n\
@@ -35,28 +38,28 @@ lazy_static! {
                      <div id=\"I_file\"[^>]*>(.*?)</div>\\s*\
                      <h1>(.*?)</h1>\\s*\
                      <div id=\"I_description\"[^>]*>(.*?)</div>").unwrap(),
-         format!(".TH \"$3\" 3 \"{}\" \"cplusplus.com\" \"C++ Programmer\\'s Manual\"\n\
+         format!(".TH \"$3\" 3 \"{}\" \"cplusplus.com\" \"C++ Programmer\\(aqs Manual\"\n\
                   \n.SH \"NAME\"\n$3 - $4\n\
                   \n.SE\n.SH \"TYPE\"\n$1\n\
                   \n.SE\n.SH \"SYNOPSIS\"\n#include $2\n.sp\n\
                   \n.SE\n.SH \"DESCRIPTION\"\n", Local::today().naive_local())),
         (Regex::new("(?s)\\s*<div id=\"I_type\"[^>]*>(.*?)\\s*</div>\\s*<h1>(.*?)</h1>\\s*\
                      <div id=\"I_description\"[^>]*>(.*?)</div>").unwrap(),
-         format!(".TH \"$2\" 3 \"{}\" \"cplusplus.com\" \"C++ Programmer\\'s Manual\"\n\
+         format!(".TH \"$2\" 3 \"{}\" \"cplusplus.com\" \"C++ Programmer\\(aqs Manual\"\n\
                   \n.SH \"NAME\"\n$2 - $3\n\
                   \n.SE\n.SH \"TYPE\"\n$1\n\
                   \n.SE\n.SH \"DESCRIPTION\"\n", Local::today().naive_local())),
         (Regex::new("(?s)\\s*<div id=\"I_type\"[^>]*>(.*?)\\s*</div>\\s*<h1>(.*?)</h1>\\s*\
                      <div id=\"I_file\"[^>]*>(.*?)</div>\\s*<div id=\"I_description\"[^>]*>\
                      (.*?)</div>").unwrap(),
-         format!(".TH \"$2\" 3 \"{}\" \"cplusplus.com\" \"C++ Programmer\\'s Manual\"\n\
+         format!(".TH \"$2\" 3 \"{}\" \"cplusplus.com\" \"C++ Programmer\\(aqs Manual\"\n\
                   \n.SH \"NAME\"\n$2 - $4\n\
                   \n.SE\n.SH \"TYPE\"\n$1\n\
                   \n.SE\n.SH \"DESCRIPTION\"\n", Local::today().naive_local())),
         (Regex::new("(?s)\\s*<div id=\"I_type\"[^>]*>(.*?)\\s*</div>\\s*<h1>(.*?)</h1>\\s*\
                      <div class=\"C_prototype\"[^>]*>(.*?)</div>\\s*\
                      <div id=\"I_description\"[^>]*>(.*?)</div>").unwrap(),
-         format!(".TH \"$2\" 3 \"{}\" \"cplusplus.com\" \"C++ Programmer\\'s Manual\"\n\
+         format!(".TH \"$2\" 3 \"{}\" \"cplusplus.com\" \"C++ Programmer\\(aqs Manual\"\n\
                   \n.SH \"NAME\"\n$2 - $4\n\
                   \n.SE\n.SH \"TYPE\"\n$1\n\
                   \n.SE\n.SH \"SYNOPSIS\"\n$3\n\
@@ -91,7 +94,7 @@ lazy_static! {
         // Footer
         (Regex::new("(?s)<div id=\"CH_bb\">.*$").unwrap(),
          "\n.SE\n.SH \"REFERENCE\"\n\
-          cplusplus.com, 2000-2015 - All rights reserved.".to_owned()),
+          cplusplus.com, 2000\\-2015 \\- All rights reserved.".to_owned()),
         // C++ version tag
         (Regex::new("<div.+?title=\"(C\\+\\+..)\"[^>]*>").unwrap(), ".sp\n$1\n".to_owned()),
         // 'br' tag
@@ -107,22 +110,24 @@ lazy_static! {
         (Regex::new("<script[^>]*>[^<]*</script>").unwrap(), "".to_owned()),
         (Regex::new("=\"([^>\"]*)>([^\"]*)\"").unwrap(), "=\"$1&gt;$2\"".to_owned()),
         (Regex::new("(?s)<.*?>").unwrap(), "".to_owned()),
+    ];
+
+    // Applied once character references are decoded
+    static ref POST_RPS: Vec<(Regex, String)> = vec![
         // Misc
-        (Regex::new("&lt;").unwrap(), "<".to_owned()),
-        (Regex::new("&gt;").unwrap(), ">".to_owned()),
-        (Regex::new("&quot;").unwrap(), "\"".to_owned()),
-        (Regex::new("&amp;").unwrap(), "&".to_owned()),
-        (Regex::new("&nbsp;").unwrap(), " ".to_owned()),
-        (Regex::new("\\\\([^\\^nE])").unwrap(), "\\\\$1".to_owned()),
         (Regex::new(">/\">").unwrap(), "".to_owned()),
         (Regex::new("/\">").unwrap(), "".to_owned()),
         // Remove empty lines
         (Regex::new("\n\\s*\n+").unwrap(), "\n".to_owned()),
         (Regex::new("\n\n+").unwrap(), "\n".to_owned()),
-        // Preserve \n" in EXAMPLE
-        (Regex::new("\\\\n").unwrap(), "\\en".to_owned()),
     ];
 
+    static ref INFO_TYPE: Regex = Regex::new("(?s)<div id=\"I_type\"[^>]*>(.*?)</div>").unwrap();
+    static ref INFO_FILE: Regex = Regex::new("(?s)<div id=\"I_file\"[^>]*>(.*?)</div>").unwrap();
+    static ref INFO_VERSION: Regex = Regex::new("class=\"C_ico cpp(\\d\\d)warning\"").unwrap();
+    static ref HEADER_NAME: Regex = Regex::new("<\\s*([\\w./]+)\\s*>").unwrap();
+    static ref ANY_TAG: Regex = Regex::new("(?s)<[^>]*>").unwrap();
+
     static ref SECTION_HEADER: Regex = Regex::new(".SH .*\n").unwrap();
 
     static ref PAGE_TYPE: Regex = Regex::new("\n\\.SH \"TYPE\"\n(.+?)\n").unwrap();
@@ -135,6 +140,16 @@ lazy_static! {
 
     static ref INHERITED_INHERIT: Regex = Regex::new(".+?INHERITED FROM (.+)").unwrap();
     static ref INHERITED_NONAME: Regex = Regex::new("\n\\.IP \"(.+)\"").unwrap();
+
+    static ref SECTION: Regex = Regex::new("(?m)^\\.SH \"(.+?)\"$").unwrap();
+    static ref MEMBER_ENTRY: Regex = Regex::new("(?m)^\\.IP \"(.+?)\\(3\\)[^\"]*\"\n(.*)$").unwrap();
+    static ref GROFF_TABLE: Regex = Regex::new("(?s)\\.TS\n.*?\\.TE").unwrap();
+    static ref EXAMPLE_SECTION: Regex =
+        Regex::new("(?is)<h3>\\s*Example\\s*</h3>(.*?)(?:<h3>|<div id=\"CH_bb\"|$)").unwrap();
+    static ref SNIPPET: Regex =
+        Regex::new("(?s)<table class=\"snippet\">.*?<pre[^>]*>(.*?)</pre>.*?</table>").unwrap();
+    static ref SNIPPET_OUTPUT: Regex = Regex::new("(?s)Output:?.*?<pre[^>]*>(.*?)</pre>").unwrap();
+    static ref TABLE_ROW: Regex = Regex::new("(?ms)^T\\{\n(.*?)\nT\\}\\|T\\{\n(.*?)\nT\\}").unwrap();
 }
 
 
@@ -152,16 +167,16 @@ pub fn html2groff(data: &str, _name: &str) -> String {
         data = data[pos..].to_owned();
     }
 
+    // Text is escaped while it can still be told from markup
+    data = escape::html(&data);
+
     // Pre replace all
     for &(ref reg, ref repl) in PRE_RPS.iter() {
         data = reg.replace_all(&data, repl.as_str()).into_owned();
     }
 
-    let (ref ec_reg, ref ec_repl) = *ESCAPE_COLUMN.deref();
     data = TABLE.replace_all(&data, |c: &Captures| {
-        let mut parsed_table = parse_table(&escape_pre_section(&c[0])).unwrap();
-        parsed_table = ec_reg.replace_all(&parsed_table, ec_repl.as_str()).into_owned();
-        parsed_table
+        parse_table(&escape_pre_section(&c[0])).unwrap()
     }).into_owned();
 
     // Replace all
@@ -169,8 +184,13 @@ pub fn html2groff(data: &str, _name: &str) -> String {
         data = reg.replace_all(&data, repl.as_str()).into_owned();
     }
 
+    data = decode_entities(&data);
+    for &(ref reg, ref repl) in POST_RPS.iter() {
+        data = reg.replace_all(&data, repl.as_str()).into_owned();
+    }
+
     // Upper case all section headers
-    data = SECTION_HEADER.replace_all(&data, |c: &Captures| c[0].to_uppercase()).into_owned();
+    data = SECTION_HEADER.replace_all(&data, |c: &Captures| escape::to_uppercase(&c[0])).into_owned();
 
     // Quote from the original cppman:
     //
@@ -201,12 +221,12 @@ pub fn html2groff(data: &str, _name: &str) -> String {
                         !sec.contains("INHERITED") &&
                         sec != "MEMBER TYPES" {
                     let mut content2 = MEMBER_NONAME.replace_all(content,
-                        format!("\n.IP \"{}::$1\"", content).as_str()).into_owned();
+                        format!("\n.IP \"{}::$1\"", class_name).as_str()).into_owned();
                     // Replace (constructor) (destructor)
                     content2 = MEMBER_CONSTRUCTOR.replace_all(&content2,
                         format!("{}", class_name).as_str()).into_owned();
                     content2 = MEMBER_DESTRUCTOR.replace_all(&content2,
-                        format!("~{}", class_name).as_str()).into_owned();
+                        format!("\\(ti{}", class_name).as_str()).into_owned();
                     data = data.replace(content, &content2);
                 // Inherited member functions
                 } else if sec.contains("MEMBER") && sec.contains("INHERITED") {
@@ -226,6 +246,244 @@ pub fn html2groff(data: &str, _name: &str) -> String {
 }
 
 
+/// Kind, header and standard version of a cplusplus.com page, from the
+/// `I_type` and `I_file` boxes and C++11 marks above the description.
+/// Pages without a mark are part of C++98.
+pub fn page_info(data: &str) -> PageInfo {
+    let head = match data.find("id=\"I_description\"") {
+        Some(pos) => &data[..pos],
+        None      => data,
+    };
+
+    let kind = INFO_TYPE.captures(head).and_then(|c| {
+        let info_type = ANY_TAG.replace_all(&c[1], "").trim().to_lowercase();
+        kind_of(&info_type)
+    });
+
+    let header = INFO_FILE.captures(head).and_then(|c| {
+        let file = decode_entities(&ANY_TAG.replace_all(&c[1], ""));
+        HEADER_NAME.captures(&file).map(|h| format!("<{}>", &h[1]))
+    });
+
+    let std_version = match INFO_VERSION.captures(head) {
+        Some(c) => StdVersion::try_from(&format!("c++{}", &c[1])).ok(),
+        None    => Some(StdVersion::Cpp98),
+    };
+
+    PageInfo { kind: kind, header: header, std_version: std_version }
+}
+
+/// Members and bases of the class described by `groff_text`, the output of
+/// `html2groff`, or `None` if it isn't a class page.
+///
+/// Member functions come from the `class::member(3)` entries `html2groff`
+/// writes, member types from the first column of the tables in their
+/// section, and bases from the sections of inherited members.
+pub fn class_info(groff_text: &str) -> Option<ClassInfo> {
+    match PAGE_TYPE.captures(groff_text) {
+        Some(ref c) if c[1].contains("class") => {},
+        _                                     => return None,
+    }
+
+    let headers = SECTION.captures_iter(groff_text).collect::<Vec<_>>();
+    let mut info = ClassInfo::default();
+
+    for (i, header) in headers.iter().enumerate() {
+        let title = &header[1];
+        if !title.contains("MEMBER") || title.contains("NON-MEMBER") {
+            continue;
+        }
+
+        let start = header.get(0).unwrap().end();
+        let end = headers.get(i + 1).map_or(groff_text.len(), |h| h.get(0).unwrap().start());
+        let body = &groff_text[start..end];
+
+        let origin = INHERITED_INHERIT.captures(title).map(|c| escape::unescape(c[1].trim()).to_lowercase());
+        if let Some(ref base) = origin {
+            if !info.bases.contains(base) {
+                info.bases.push(base.clone());
+            }
+        }
+
+        let list = match title.find(" INHERITED") {
+            Some(pos) => &title[..pos],
+            None      => title,
+        };
+        let kind = kind_of(&list.to_lowercase()).unwrap_or(Kind::Function);
+
+        let mut member = |name: &str, description: &str| {
+            let name = match name.rfind("::") {
+                Some(pos) => &name[pos+2..],
+                None      => name,
+            };
+            info.members.push(Member {
+                kind: kind,
+                name: escape::unescape(name.trim()),
+                origin: origin.clone(),
+                description: escape::unescape(&description.split_whitespace().collect::<Vec<_>>().join(" ")),
+            });
+        };
+
+        for entry in MEMBER_ENTRY.captures_iter(body) {
+            member(&entry[1], &entry[2]);
+        }
+
+        for table in GROFF_TABLE.find_iter(body) {
+            // The first row holds the column titles
+            for row in TABLE_ROW.captures_iter(table.as_str()).skip(1) {
+                member(&cell_text(&row[1]), &cell_text(&row[2]));
+            }
+        }
+    }
+
+    Some(info)
+}
+
+/// Source of the example of a cplusplus.com page, the snippet in its Example
+/// section, and the output shown below it.
+pub fn example(data: &str) -> Option<Example> {
+    let section = match EXAMPLE_SECTION.captures(data) {
+        Some(c) => c.get(1).unwrap().as_str(),
+        None    => return None,
+    };
+    let snippet = match SNIPPET.captures(section) {
+        Some(c) => c,
+        None    => return None,
+    };
+
+    let mut code = pre_text(&snippet[1]);
+    if !code.ends_with('\n') {
+        code.push('\n');
+    }
+
+    let after = &section[snippet.get(0).unwrap().end()..];
+    let output = SNIPPET_OUTPUT.captures(after).map(|c| pre_text(&c[1]));
+
+    Some(Example { code: code, output: output })
+}
+
+/// Text of a table cell on a single line, without groff requests, still
+/// escaped.
+fn cell_text(cell: &str) -> String {
+    cell.lines()
+        .filter(|l| !l.starts_with('.'))
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Map an `I_type` description such as "public member function" to a kind.
+fn kind_of(info_type: &str) -> Option<Kind> {
+    if info_type.contains("macro") {
+        Some(Kind::Macro)
+    } else if info_type.contains("function") {
+        Some(Kind::Function)
+    } else if ["class", "struct", "union"].iter().any(|k| info_type.contains(k)) {
+        Some(Kind::Class)
+    } else if info_type.contains("type") {
+        Some(Kind::TypeAlias)
+    } else if ["constant", "object", "variable"].iter().any(|k| info_type.contains(k)) {
+        Some(Kind::Object)
+    } else if info_type.contains("namespace") {
+        Some(Kind::Namespace)
+    } else if info_type.contains("header") || info_type.contains("library") {
+        Some(Kind::Header)
+    } else {
+        None
+    }
+}
+
+
+#[test]
+fn test_page_info() {
+    let info = page_info("<div class=\"C_doc\"><div id=\"I_type\">class template\
+                          <span alt=\"cpp11\" class=\"C_ico cpp11warning\" title=\"C++11\"></span></div>\
+                          <div id=\"I_file\">&lt;array&gt;</div><h1>std::array</h1>\
+                          <div id=\"I_description\">Array class</div>");
+    assert!(info.kind == Some(Kind::Class));
+    assert_eq!(info.header, Some("<array>".to_owned()));
+    assert!(info.std_version == Some(StdVersion::Cpp11));
+
+    let info = page_info("<div id=\"I_type\">public member function</div><h1>vector::push_back</h1>\
+                          <div id=\"I_description\">Add element at the end</div>");
+    assert!(info.kind == Some(Kind::Function));
+    assert_eq!(info.header, None);
+    assert!(info.std_version == Some(StdVersion::Cpp98));
+}
+
+#[test]
+fn test_class_info() {
+    let html = "<div class=\"C_doc\"><div id=\"I_type\">class</div>\n\
+                <div id=\"I_file\">&lt;istream&gt;</div>\n<h1>std::istream</h1>\n\
+                <div class=\"C_prototype\"><pre>typedef basic_istream&lt;char&gt; istream;</pre></div>\n\
+                <div id=\"I_description\">Input stream</div>\n\
+                <h3>Member types</h3>\n\
+                <table class=\"boxed\"><tr><th>member type</th><th>definition</th></tr>\
+                <tr><td>char_type</td><td>char</td></tr></table>\n\
+                <h3>Public member functions</h3>\n\
+                <dl class=\"links\"><dt><a href=\"/istream/istream/\"><b>(constructor)</b></a></dt>\
+                <dd>Construct object <span class=\"typ\">(public member function)</span></dd></dl>\n\
+                <dl class=\"links\"><dt><a href=\"/istream/gcount/\"><b>gcount</b></a></dt>\
+                <dd>Get character count <span class=\"typ\">(public member function)</span></dd></dl>\n\
+                <h3>Public member functions inherited from ios</h3>\n\
+                <dl class=\"links\"><dt><a href=\"/ios/good/\"><b>good</b></a></dt>\
+                <dd>Check whether state of stream is good <span class=\"typ\">(public member function)</span></dd></dl>\n\
+                <h3>Public member functions inherited from ios_base</h3>\n\
+                <dl class=\"links\"><dt><a href=\"/ios_base/flags/\"><b>flags</b></a></dt>\
+                <dd>Get/set format flags <span class=\"typ\">(public member function)</span></dd></dl>\n\
+                <div id=\"CH_bb\"></div></div>";
+    let groff_text = html2groff(html, "istream");
+    assert!(groff_text.contains(".IP \"istream::gcount(3)\""));
+
+    let info = class_info(&groff_text).unwrap();
+    assert_eq!(info.bases, vec!["ios", "ios_base"]);
+    let members = info.members.iter()
+        .map(|m| (m.kind, m.name.as_str(), m.origin.as_ref().map(String::as_str)))
+        .collect::<Vec<_>>();
+    assert_eq!(members, vec![(Kind::TypeAlias, "char_type", None),
+                             (Kind::Function, "istream", None),
+                             (Kind::Function, "gcount", None),
+                             (Kind::Function, "good", Some("ios")),
+                             (Kind::Function, "flags", Some("ios_base"))]);
+    assert_eq!(info.members[2].description, "Get character count (public member function)");
+
+    assert_eq!(class_info(".TH \"printf\" 3\n.SH \"TYPE\"\nfunction\n"), None);
+}
+
+#[test]
+fn test_escaping() {
+    let html = "<div class=\"C_doc\"><div id=\"I_type\">function</div>\n\
+                <div id=\"I_file\">&lt;cstdio&gt;</div>\n<h1>putchar</h1>\n\
+                <div id=\"I_description\">Write character to stdout</div>\n\
+                <h3>Example</h3>\n<pre>putchar ('\\n');\n.x = a-b;</pre>\n\
+                <table><tr><th>value</th></tr><tr><td>.5 \"c\"</td></tr></table>\n\
+                <div id=\"CH_bb\"></div></div>";
+    let groff_text = html2groff(html, "putchar");
+    assert!(groff_text.contains("C++ Programmer\\(aqs Manual"));
+    assert!(groff_text.contains("\n.nf\nputchar (\\(aq\\en\\(aq);\n\\&.x = a\\-b;\n.fi\n"));
+    assert!(groff_text.contains("T{\n\\&.5 \\(dqc\\(dq\nT}"));
+}
+
+#[test]
+fn test_example() {
+    let html = "<div id=\"I_description\">Return iterator to beginning</div>\
+                <section id=\"example\"><h3>Example</h3>\n\
+                <table class=\"snippet\"><tbody><tr><td class=\"rownum\"><var>1<br>2<br>3</var></td>\
+                <td class=\"source\"><pre><code><cite>// vector::begin</cite>\n\
+                <dfn>#include</dfn> &lt;iostream&gt;\n\
+                <var>int</var> main () { std::cout &lt;&lt; <kbd>\"a &amp; b\\n\"</kbd>; }</code></pre></td>\
+                </tr></tbody></table>\n\
+                Output:<br>\n<pre>a &amp; b\n</pre></section>\n\
+                <h3>Complexity</h3>\n<div id=\"CH_bb\"></div>";
+
+    let found = example(html).unwrap();
+    assert_eq!(found.code, "// vector::begin\n#include <iostream>\n\
+                              int main () { std::cout << \"a & b\\n\"; }\n");
+    assert_eq!(found.output, Some("a & b\n".to_owned()));
+
+    assert_eq!(example("<h3>Complexity</h3>Constant."), None);
+}
+
 #[test]
 /// Test if there is major format changes in cplusplus.com
 fn func_test() {
@@ -1,3 +1,87 @@
-pub fn html2groff(data: &str, _name: &str) -> String {
-    unimplemented!();
+use regex::Regex;
+
+use ::config::{Source, StdVersion};
+use ::errors;
+use ::formatter::entities::decode_entities;
+use ::index::{Kind, PageInfo};
+
+
+lazy_static! {
+    static ref DEFINED_IN: Regex = Regex::new("(?s)Defined in header\\s*<code>(.*?)</code>").unwrap();
+    static ref DECLARATION: Regex = Regex::new("(?s)<tr class=\"t-dcl[ \"].*?</tr>").unwrap();
+    static ref SINCE: Regex = Regex::new("t-since-cxx(\\d\\d)").unwrap();
+    static ref HEADER_NAME: Regex = Regex::new("<\\s*([\\w./]+)\\s*>").unwrap();
+    static ref ANY_TAG: Regex = Regex::new("(?s)<[^>]*>").unwrap();
+
+    static ref CLASS: Regex = Regex::new("(?s)^\\s*(template\\s*<.*?>\\s*)?(class|struct|union)\\s").unwrap();
+    static ref TYPE_ALIAS: Regex = Regex::new("\\busing\\s+[\\w:]+\\s*=|\\btypedef\\s").unwrap();
+}
+
+
+/// Not written yet: pages of cppreference.com can be indexed, not shown.
+pub fn html2groff(_data: &str, _name: &str) -> errors::Result<String> {
+    Err(errors::ErrorKind::FormatterNotImplemented(Source::CppReference.to_string()).into())
+}
+
+/// Kind, header and standard version of a cppreference.com page, from the
+/// "Defined in header" line and the first declaration. A declaration without
+/// a "(since C++xx)" mark is part of C++98.
+pub fn page_info(data: &str) -> PageInfo {
+    let header = DEFINED_IN.captures(data).and_then(|c| {
+        HEADER_NAME.captures(&plain_text(&c[1])).map(|h| format!("<{}>", &h[1]))
+    });
+
+    let declaration = DECLARATION.find(data).map(|m| m.as_str());
+
+    let kind = declaration.and_then(|d| kind_of(&plain_text(d)));
+    let std_version = declaration.map(|d| {
+        SINCE.captures(d)
+            .and_then(|c| StdVersion::try_from(&format!("c++{}", &c[1])).ok())
+            .unwrap_or(StdVersion::Cpp98)
+    });
+
+    PageInfo { kind: kind, header: header, std_version: std_version }
+}
+
+/// Kind of the entity declared by `declaration`.
+fn kind_of(declaration: &str) -> Option<Kind> {
+    if declaration.contains("#define") {
+        Some(Kind::Macro)
+    } else if declaration.contains("concept ") {
+        Some(Kind::Concept)
+    } else if CLASS.is_match(declaration) {
+        Some(Kind::Class)
+    } else if TYPE_ALIAS.is_match(declaration) {
+        Some(Kind::TypeAlias)
+    } else if declaration.contains("namespace ") {
+        Some(Kind::Namespace)
+    } else if declaration.contains('(') {
+        Some(Kind::Function)
+    } else if declaration.trim().is_empty() {
+        None
+    } else {
+        Some(Kind::Object)
+    }
+}
+
+fn plain_text(html: &str) -> String {
+    decode_entities(&ANY_TAG.replace_all(html, "")).replace('\u{a0}', " ")
+}
+
+
+#[test]
+fn test_page_info() {
+    let info = page_info("<tr class=\"t-dsc-header\"><td>Defined in header <code>\
+                          <a href=\"../header/utility.html\">&lt;utility&gt;</a></code></td></tr>\
+                          <tr class=\"t-dcl t-since-cxx11\"><td><span>template&lt; class T &gt;\n\
+                          typename std::remove_reference&lt;T&gt;::type&amp;&amp; move( T&amp;&amp; t );\
+                          </span></td><td><span class=\"t-mark-rev t-since-cxx11\">(since C++11)</span></td></tr>");
+    assert!(info.kind == Some(Kind::Function));
+    assert_eq!(info.header, Some("<utility>".to_owned()));
+    assert!(info.std_version == Some(StdVersion::Cpp11));
+
+    let info = page_info("<tr class=\"t-dcl\"><td>template&lt;\n class T,\n class Allocator = \
+                          std::allocator&lt;T&gt;\n&gt; class vector;</td></tr>");
+    assert!(info.kind == Some(Kind::Class));
+    assert!(info.std_version == Some(StdVersion::Cpp98));
 }
@@ -0,0 +1,355 @@
+//! Escaping of text put in groff pages.
+//!
+//! Text lines and macro arguments are escaped differently: a text line must
+//! not start with a control character, and a quoted argument must not contain
+//! a quote or a line break. Characters that groff renders as something else
+//! on UTF-8 terminals, such as `-` as a hyphen and `'` as a closing quote,
+//! are written as the escape of the ASCII character in both.
+
+use std::char;
+
+use regex::{Captures, Regex};
+
+use ::formatter::entities::decode_entities;
+
+
+lazy_static! {
+    static ref MARKUP: Regex = Regex::new("(?s)<!--.*?-->|<[A-Za-z/!](?:[^>\"']|\"[^\"]*\"|'[^']*')*>").unwrap();
+    static ref ESCAPE: Regex = Regex::new("\\\\(?:\\((..)|\\[([^\\]]*)\\]|(.))").unwrap();
+}
+
+
+/// Escapes of the ASCII characters that groff doesn't output as themselves.
+const ASCII_ESCAPES: &'static [(char, &'static str)] = &[
+    ('"', "\\(dq"), ('\'', "\\(aq"), ('-', "\\-"), ('\\', "\\e"), ('^', "\\(ha"),
+    ('`', "\\(ga"), ('~', "\\(ti"),
+];
+
+/// Groff names of characters that pages commonly use, rendered sensibly by
+/// both the ASCII and the UTF-8 output devices. Sorted by character.
+const SPECIAL_CHARS: &'static [(char, &'static str)] = &[
+    ('\u{a0}', " "),
+    ('\u{a9}', "\\(co"), ('\u{ae}', "\\(rg"), ('\u{b0}', "\\(de"), ('\u{b1}', "\\(+-"),
+    ('\u{b7}', "\\(pc"), ('\u{d7}', "\\(mu"), ('\u{f7}', "\\(di"),
+    ('\u{2013}', "\\(en"), ('\u{2014}', "\\(em"), ('\u{2018}', "\\(oq"), ('\u{2019}', "\\(cq"),
+    ('\u{201c}', "\\(lq"), ('\u{201d}', "\\(rq"), ('\u{2022}', "\\(bu"), ('\u{2026}', "..."),
+    ('\u{2122}', "\\(tm"), ('\u{2190}', "\\(<-"), ('\u{2192}', "\\(->"), ('\u{2194}', "\\(<>"),
+    ('\u{21d0}', "\\(lA"), ('\u{21d2}', "\\(rA"), ('\u{21d4}', "\\(hA"), ('\u{2212}', "\\(mi"),
+    ('\u{221a}', "\\(sr"), ('\u{221e}', "\\(if"), ('\u{2248}', "\\(~~"), ('\u{2260}', "\\(!="),
+    ('\u{2261}', "\\(=="), ('\u{2264}', "\\(<="), ('\u{2265}', "\\(>="),
+];
+
+
+/// Escape `text` to be output as it is by text lines.
+pub fn text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    push_text(&mut escaped, text, false);
+    escaped
+}
+
+/// Escape `text` as a quoted macro argument, quotes included. Line breaks
+/// become spaces.
+pub fn arg(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push(' '),
+            _    => push_char(&mut escaped, c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Escape the text of `html` like `text`, leaving tags and comments as they
+/// are, so that the formatters can turn the tags into requests afterwards.
+///
+/// Character references are decoded to be escaped, except those of `&`, `<`
+/// and `>`, which are kept as references not to be taken for markup. As tags
+/// may end up at the start of lines, so may the text after them.
+pub fn html(html: &str) -> String {
+    let mut escaped = String::with_capacity(html.len());
+    let mut last = 0;
+
+    for markup in MARKUP.find_iter(html) {
+        push_text(&mut escaped, &decode_entities(&html[last..markup.start()]), true);
+        escaped.push_str(markup.as_str());
+        last = markup.end();
+    }
+    push_text(&mut escaped, &decode_entities(&html[last..]), true);
+
+    escaped
+}
+
+/// Guard the lines of a tbl text block, groff already, that tbl would take
+/// for the end of the block.
+pub fn cell(groff_text: &str) -> String {
+    groff_text.split('\n')
+        .map(|l| if l.starts_with("T}") { format!("\\&{}", l) } else { l.to_owned() })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Write the non-ASCII characters of `groff_text` as groff escapes, so that
+/// the page renders the same whatever the input encoding groff assumes. The
+/// characters groff has no name for are given by code point, `\[u2282]`.
+pub fn special_chars(groff_text: &str) -> String {
+    let mut escaped = String::with_capacity(groff_text.len());
+    for c in groff_text.chars() {
+        if (c as u32) < 0x80 {
+            escaped.push(c);
+            continue;
+        }
+
+        match SPECIAL_CHARS.binary_search_by_key(&c, |&(special, _)| special) {
+            Ok(i)  => escaped.push_str(SPECIAL_CHARS[i].1),
+            Err(_) => escaped.push_str(&format!("\\[u{:04X}]", c as u32)),
+        }
+    }
+    escaped
+}
+
+/// Upper case the text of `groff_text`, leaving escapes as they are.
+pub fn to_uppercase(groff_text: &str) -> String {
+    let mut upper = String::with_capacity(groff_text.len());
+    let mut last = 0;
+
+    for escape in ESCAPE.find_iter(groff_text) {
+        upper.push_str(&groff_text[last..escape.start()].to_uppercase());
+        upper.push_str(escape.as_str());
+        last = escape.end();
+    }
+    upper.push_str(&groff_text[last..].to_uppercase());
+
+    upper
+}
+
+/// Text that `groff_text` stands for, undoing `text`, `arg` and
+/// `special_chars`. Escapes of other characters are kept.
+pub fn unescape(groff_text: &str) -> String {
+    ESCAPE.replace_all(groff_text, |c: &Captures| {
+        let name = c.get(1).or(c.get(2)).map(|m| m.as_str());
+        match (name, c.get(3).map(|m| m.as_str())) {
+            (_, Some("e")) | (_, Some("E")) => "\\".to_owned(),
+            (_, Some("-"))                  => "-".to_owned(),
+            (_, Some("&"))                  => "".to_owned(),
+            (Some(name), _)                 => match char_named(name) {
+                Some(c) => c.to_string(),
+                None    => c[0].to_owned(),
+            },
+            _                               => c[0].to_owned(),
+        }
+    }).into_owned()
+}
+
+
+/// Character of the groff escape `\(name` or `\[name]`.
+fn char_named(name: &str) -> Option<char> {
+    if name.len() > 1 && name.starts_with('u') {
+        if let Ok(code) = u32::from_str_radix(&name[1..], 16) {
+            return char::from_u32(code);
+        }
+    }
+
+    let escape = if name.len() == 2 { format!("\\({}", name) } else { format!("\\[{}]", name) };
+    ASCII_ESCAPES.iter().chain(SPECIAL_CHARS.iter())
+        .find(|&&(_, e)| e == escape)
+        .map(|&(c, _)| c)
+}
+
+fn push_char(escaped: &mut String, c: char) {
+    match ASCII_ESCAPES.iter().find(|&&(special, _)| special == c) {
+        Some(&(_, escape)) => escaped.push_str(escape),
+        None               => escaped.push(c),
+    }
+}
+
+/// Push escaped `text`, which starts a line. A `.` preceded by nothing but
+/// blanks on its line is guarded, as removing the blanks would make a
+/// request of the line. With `html`, `&`, `<` and `>` are pushed as character
+/// references.
+fn push_text(escaped: &mut String, text: &str, html: bool) {
+    let mut line_start = true;
+    for c in text.chars() {
+        if c == '.' && line_start {
+            escaped.push_str("\\&");
+        }
+        match c {
+            '&' if html => escaped.push_str("&amp;"),
+            '<' if html => escaped.push_str("&lt;"),
+            '>' if html => escaped.push_str("&gt;"),
+            _           => push_char(escaped, c),
+        }
+
+        line_start = match c {
+            '\n'       => true,
+            ' ' | '\t' => line_start,
+            _          => false,
+        };
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::process::{Command, Stdio};
+
+    use quickcheck::quickcheck;
+
+    use super::{arg, cell, html, special_chars, text, to_uppercase, unescape};
+
+    /// Options rendering man pages on one long line per paragraph.
+    const MAN: &'static [&'static str] = &["-t", "-m", "man", "-rLL=1000n", "-rLT=1000n"];
+
+    /// `input` rendered by groff with `options` for ASCII terminals, as plain
+    /// text. Panics if groff can't be run, the tests using it being ignored
+    /// unless asked for.
+    fn groff(options: &[&str], input: &str) -> String {
+        let mut child = Command::new("groff")
+            .args(&["-Tascii", "-P-cbou"])
+            .args(options)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap_or_else(|e| panic!("cannot run groff: {}", e));
+
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+        let mut output = String::new();
+        child.stdout.take().unwrap().read_to_string(&mut output).unwrap();
+        child.wait().unwrap();
+        output
+    }
+
+    /// Line breaks and printable ASCII characters of `s`, which groff renders
+    /// as themselves.
+    fn printable(s: &str) -> String {
+        s.chars().filter(|&c| c == '\n' || (' ' <= c && c <= '~')).collect()
+    }
+
+    /// Words of `text`.
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(ToOwned::to_owned).collect()
+    }
+
+    /// Words of the man page `rendered`, without its header and footer.
+    fn body_words(rendered: &str) -> Vec<String> {
+        let lines = rendered.lines().filter(|l| !l.trim().is_empty()).collect::<Vec<_>>();
+        if lines.len() < 2 {
+            return Vec::new();
+        }
+        words(&lines[1..lines.len()-1].join("\n"))
+    }
+
+    /// Lines of `text` without trailing blanks and blank lines at the end.
+    fn lines(text: &str) -> Vec<String> {
+        let mut lines = text.lines().map(|l| l.trim_right().to_owned()).collect::<Vec<_>>();
+        while lines.last().map_or(false, |l| l.is_empty()) {
+            lines.pop();
+        }
+        lines
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(text(".TH x\n 'a' - \"b\"\\n ~^`"),
+                   "\\&.TH x\n \\(aqa\\(aq \\- \\(dqb\\(dq\\en \\(ti\\(ha\\(ga");
+        assert_eq!(arg("a \"b\"\nc\\"), "\"a \\(dqb\\(dq c\\e\"");
+        assert_eq!(cell("T}\nT{\n.br"), "\\&T}\nT{\n.br");
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(html("<td class=\"a-b\">.x</td><!-- a-b -->a-b &amp;&#39;&lt;&#92;n&gt;"),
+                   "<td class=\"a-b\">\\&.x</td><!-- a-b -->a\\-b &amp;\\(aq&lt;\\en&gt;");
+        assert_eq!(html("<a title=\"1 > 0\">'a'</a>"), "<a title=\"1 > 0\">\\(aqa\\(aq</a>");
+        assert_eq!(html("1 < 2 && 3 >= 2<br>"), "1 &lt; 2 &amp;&amp; 3 &gt;= 2<br>");
+    }
+
+    #[test]
+    fn test_special_chars() {
+        assert_eq!(special_chars("a \u{2264} b \u{2192} c\u{a0}\u{2014} d\u{2026}"),
+                   "a \\(<= b \\(-> c \\(em d...");
+        assert_eq!(special_chars("\u{e9}t\u{e9} \u{1d11e}"), "\\[u00E9]t\\[u00E9] \\[u1D11E]");
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("\\&.a \\(aqb\\(aq \\(<= \\[u00E9] \\(xx \\fBc\\fR \\E d"),
+                   ".a 'b' \u{2264} \u{e9} \\(xx \\fBc\\fR \\ d");
+        assert_eq!(to_uppercase(".SH \"Don\\(aqt \\e\""), ".SH \"DON\\(aqT \\e\"");
+    }
+
+    quickcheck! {
+        fn prop_text_unescapes(s: String) -> bool {
+            unescape(&text(&s)) == s && unescape(&arg(&s)) == format!("\"{}\"", s.replace('\n', " "))
+        }
+
+        fn prop_text_makes_no_requests(s: String) -> bool {
+            text(&s).lines().all(|l| !l.starts_with('.') && !l.starts_with('\''))
+        }
+
+        fn prop_arg_is_one_argument(s: String) -> bool {
+            let escaped = arg(&s);
+            !escaped.contains('\n') && escaped[1..escaped.len()-1].find('"').is_none()
+        }
+
+        fn prop_special_chars_unescape(s: String) -> bool {
+            // No-break spaces and ellipses are approximated
+            let s = s.replace('\u{a0}', " ").replace('\u{2026}', "...");
+            unescape(&special_chars(&text(&s))) == s
+        }
+    }
+
+    fn groff_renders_text(s: String) -> bool {
+        let s = printable(&s);
+        let rendered = groff(&[], &format!(".po 0\n.ll 1000n\n.nf\n{}\n", text(&s)));
+        lines(&rendered) == lines(&s)
+    }
+
+    fn groff_renders_title(s: String) -> bool {
+        let s = printable(&s);
+        let rendered = groff(MAN, &format!(".TH {} 3\nx\n", arg(&s)));
+        let header = rendered.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+        words(header).starts_with(&words(&format!("{}(3)", s)))
+    }
+
+    fn groff_renders_tag(s: String) -> bool {
+        let s = printable(&s);
+        let rendered = groff(MAN, &format!(".TH T 3\n.IP {} 4\nx\n", arg(&s)));
+        let mut expected = words(&s);
+        expected.push("x".to_owned());
+        body_words(&rendered) == expected
+    }
+
+    fn groff_renders_cell(s: String) -> bool {
+        let s = printable(&s);
+        let table = format!(".TS\nl.\nT{{\n{}\nT}}\n.TE", cell(&text(&s)));
+        let rendered = groff(MAN, &format!(".TH T 3\n{}\nx\n", table));
+        let mut expected = words(&s);
+        expected.push("x".to_owned());
+        body_words(&rendered) == expected
+    }
+
+    #[test]
+    #[ignore]
+    fn test_groff_renders_text() {
+        quickcheck(groff_renders_text as fn(String) -> bool);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_groff_renders_args() {
+        quickcheck(groff_renders_title as fn(String) -> bool);
+        quickcheck(groff_renders_tag as fn(String) -> bool);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_groff_renders_cells() {
+        quickcheck(groff_renders_cell as fn(String) -> bool);
+    }
+}
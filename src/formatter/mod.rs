@@ -1,4 +1,237 @@
+use regex::{Captures, Regex};
+
+use ::config::{ManSection, Source};
+use ::errors;
+use ::example::Example;
+use ::index::{ClassInfo, PageInfo};
+
 pub mod cplusplus;
 pub mod cppreference;
+mod entities;
+mod escape;
 mod tableparser;
 mod utils;
+
+pub use self::entities::decode_entities;
+pub use self::escape::special_chars;
+
+
+lazy_static! {
+    static ref OPERATOR: Regex = Regex::new("^\\s*(.*?::(?:operator)?)([^:]*)\\s*$").unwrap();
+    static ref TITLE: Regex = Regex::new("(?m)^\\.TH \"[^\"]*\" 3 ").unwrap();
+    static ref NAME_SECTION: Regex = Regex::new("(?s)\\.SH \"NAME\"\n(.*?)(\n\\.SH |$)").unwrap();
+    static ref SENTENCE_END: Regex = Regex::new("\\.(\\s|$)").unwrap();
+    static ref WHITESPACE: Regex = Regex::new("\\s+").unwrap();
+    static ref ALIAS: Regex = Regex::new("^\\.so man3/(.+)\n?$").unwrap();
+    static ref SECTION: Regex = Regex::new("(?m)^\\.SH +\"?(.*?)\"? *$").unwrap();
+}
+
+
+/// Sections printed by `--brief`.
+pub const BRIEF_SECTIONS: &'static [&'static str] = &["NAME", "SYNOPSIS"];
+
+/// Other names of sections that `select_sections` accepts, e.g. what pages
+/// of one source call what pages of the other call otherwise.
+const SECTION_ALIASES: &'static [(&'static str, &'static str)] = &[
+    ("EXCEPTIONS", "EXCEPTION SAFETY"),
+];
+
+
+/// Split a page title naming several entities into index names, e.g.
+/// `vector::operator==,!=` into `vector::operator==` and `vector::operator!=`.
+pub fn split_names(title: &str) -> Vec<String> {
+    let mut names = title.split(',').map(str::to_owned).collect::<Vec<_>>();
+
+    if names.len() > 1 {
+        let operator = OPERATOR.captures(&names[0]).map(|c| (c[1].to_owned(), c[2].to_owned()));
+        if let Some((prefix, first)) = operator {
+            names[0] = first;
+            names = names.into_iter().map(|n| prefix.clone() + &n).collect();
+        }
+    }
+
+    names.into_iter().map(|n| n.trim().to_owned()).collect()
+}
+
+/// Convert HTML text of a page from `source` to groff-formatted text.
+///
+/// `names` are the index names of the page, the one it is stored under first.
+/// All of them are listed in the NAME section so that `whatis` and `apropos`
+/// find the page by any of them.
+pub fn html2groff(source: Source, data: &str, names: &[String], section: ManSection)
+        -> errors::Result<String> {
+    let name = names.first().map_or("", String::as_str);
+    let groff_text = match source {
+        Source::CPlusPlus    => cplusplus::html2groff(data, name),
+        Source::CppReference => cppreference::html2groff(data, name)?,
+    };
+
+    Ok(special_chars(&set_page_names(&groff_text, names, section)))
+}
+
+/// Kind, header and standard version of a page from `source`, as far as
+/// they can be told from its HTML.
+pub fn page_info(source: Source, data: &str) -> PageInfo {
+    match source {
+        Source::CPlusPlus    => cplusplus::page_info(data),
+        Source::CppReference => cppreference::page_info(data),
+    }
+}
+
+/// Members and bases of the class a page from `source` describes, from its
+/// groff text, or `None` if it isn't a class page.
+pub fn class_info(source: Source, groff_text: &str) -> Option<ClassInfo> {
+    match source {
+        Source::CPlusPlus    => cplusplus::class_info(groff_text),
+        // Not converted to groff yet
+        Source::CppReference => None,
+    }
+}
+
+/// Example of a page from `source` and its documented output, from the
+/// page's HTML.
+pub fn example(source: Source, data: &str) -> Option<Example> {
+    match source {
+        Source::CPlusPlus    => cplusplus::example(data),
+        // Not supported yet
+        Source::CppReference => None,
+    }
+}
+
+/// Keep only the sections of `groff_text` with one of `names` as title,
+/// ignoring case, and the requests before the first section. `None` if the
+/// page has none of them.
+pub fn select_sections<S: AsRef<str>>(groff_text: &str, names: &[S]) -> Option<String> {
+    let names = names.iter()
+        .map(|n| WHITESPACE.replace_all(n.as_ref().trim(), " ").to_uppercase())
+        .collect::<Vec<_>>();
+    let wanted = |title: &str| {
+        let title = WHITESPACE.replace_all(title.trim(), " ").to_uppercase();
+        names.iter().any(|n| {
+            *n == title || SECTION_ALIASES.iter().any(|&(alias, name)| *n == alias && title == name)
+        })
+    };
+
+    let headers = SECTION.captures_iter(groff_text).collect::<Vec<_>>();
+    let mut selected = match headers.first() {
+        Some(first) => groff_text[..first.get(0).unwrap().start()].to_owned(),
+        None        => return None,
+    };
+
+    let mut found = false;
+    for (i, header) in headers.iter().enumerate() {
+        if !wanted(&header[1]) {
+            continue;
+        }
+
+        let start = header.get(0).unwrap().start();
+        let end = headers.get(i + 1).map_or(groff_text.len(), |h| h.get(0).unwrap().start());
+        selected.push_str(&groff_text[start..end]);
+        found = true;
+    }
+
+    if found { Some(selected) } else { None }
+}
+
+/// Page including the page stored as `target`, for the other names of a
+/// page.
+pub fn alias_page(target: &str, section: ManSection) -> String {
+    format!(".so man3/{}.{}\n", target, section)
+}
+
+/// File name of the page an alias page includes, if `groff_text` is one.
+pub fn alias_target(groff_text: &str) -> Option<&str> {
+    ALIAS.captures(groff_text).map(|c| c.get(1).unwrap().as_str())
+}
+
+/// Put `section` and the first name in the title and make the NAME section
+/// a single `name, ... \- summary` line, the format man-db indexes.
+fn set_page_names(groff_text: &str, names: &[String], section: ManSection) -> String {
+    if names.is_empty() {
+        return groff_text.to_owned();
+    }
+
+    let title = format!(".TH {} {} ", escape::arg(&names[0]), section);
+    let groff_text = TITLE.replace(groff_text, title.as_str());
+
+    let names = names.iter().map(|n| escape::text(n)).collect::<Vec<_>>().join(", ");
+    NAME_SECTION.replace(&groff_text, |c: &Captures| {
+        let end = if c[2].is_empty() { "\n" } else { &c[2] };
+        format!(".SH \"NAME\"\n{} \\- {}{}", names, summary(&c[1]), end)
+    }).into_owned()
+}
+
+/// First sentence of the description in a NAME section body.
+fn summary(body: &str) -> String {
+    let text = body.lines().filter(|l| !l.starts_with('.')).collect::<Vec<_>>().join(" ");
+    // The body starts with the page title
+    let text = match text.find(" - ") {
+        Some(pos) => &text[pos+3..],
+        None      => &text[..],
+    };
+    let text = WHITESPACE.replace_all(text.trim(), " ");
+
+    match SENTENCE_END.find(&text) {
+        Some(m) => text[..m.start()].to_owned(),
+        None    => text.into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{alias_page, alias_target, select_sections, set_page_names, split_names, BRIEF_SECTIONS};
+    use ::config::ManSection;
+
+    #[test]
+    fn test_split_names() {
+        assert_eq!(split_names("vector::operator==,!=,<"),
+                   vec!["vector::operator==", "vector::operator!=", "vector::operator<"]);
+        assert_eq!(split_names("std::begin"), vec!["std::begin"]);
+    }
+
+    #[test]
+    fn test_name_section() {
+        let groff = ".TH \"rel_ops\" 3 \"2017-06-01\" \"cplusplus.com\"\n\
+                     .SH \"NAME\"\nrel_ops - Relational Operators.\n.sp\nMore text.\n\
+                     .SH \"TYPE\"\nnamespace\n";
+        let names = vec!["std::rel_ops::operator!=".to_owned(), "std::rel_ops::operator>".to_owned()];
+
+        assert_eq!(set_page_names(groff, &names, ManSection::Man3Cpp),
+                   ".TH \"std::rel_ops::operator!=\" 3cpp \"2017-06-01\" \"cplusplus.com\"\n\
+                    .SH \"NAME\"\nstd::rel_ops::operator!=, std::rel_ops::operator> \\- Relational Operators\n\
+                    .SH \"TYPE\"\nnamespace\n");
+
+        let names = vec!["vector::~vector".to_owned(), "operator-".to_owned()];
+        assert_eq!(set_page_names(groff, &names, ManSection::Man3),
+                   ".TH \"vector::\\(tivector\" 3 \"2017-06-01\" \"cplusplus.com\"\n\
+                    .SH \"NAME\"\nvector::\\(tivector, operator\\- \\- Relational Operators\n\
+                    .SH \"TYPE\"\nnamespace\n");
+    }
+
+    #[test]
+    fn test_alias() {
+        let page = alias_page("vector::begin", ManSection::Man3);
+        assert_eq!(page, ".so man3/vector::begin.3\n");
+        assert_eq!(alias_target(&page), Some("vector::begin.3"));
+        assert_eq!(alias_target(".TH \"x\" 3\n.so man3/y.3\n"), None);
+    }
+
+    #[test]
+    fn test_select_sections() {
+        let groff = ".TH \"abs\" 3 \"2017-06-01\" \"cplusplus.com\"\n\
+                     .SH \"NAME\"\nabs \\- Absolute value\n\
+                     .SH \"SYNOPSIS\"\n#include <cstdlib>\n.sp\nint abs (int n);\n\
+                     .SH \"RETURN VALUE\"\nThe absolute value of n.\n\
+                     .SH \"EXCEPTION SAFETY\"\nNo-throw guarantee.\n";
+
+        assert_eq!(select_sections(groff, BRIEF_SECTIONS).unwrap(),
+                   ".TH \"abs\" 3 \"2017-06-01\" \"cplusplus.com\"\n\
+                    .SH \"NAME\"\nabs \\- Absolute value\n\
+                    .SH \"SYNOPSIS\"\n#include <cstdlib>\n.sp\nint abs (int n);\n");
+        assert_eq!(select_sections(groff, &["return  value", "Exceptions"]).unwrap(),
+                   ".TH \"abs\" 3 \"2017-06-01\" \"cplusplus.com\"\n\
+                    .SH \"RETURN VALUE\"\nThe absolute value of n.\n\
+                    .SH \"EXCEPTION SAFETY\"\nNo-throw guarantee.\n");
+        assert_eq!(select_sections(groff, &["COMPLEXITY"]), None);
+    }
+}
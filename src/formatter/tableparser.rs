@@ -6,6 +6,7 @@ use either::{Either, Left, Right};
 use nom::{IResult, GetInput};
 use regex::{Regex, Captures};
 
+use ::formatter::escape;
 use ::formatter::utils::{HtmlError, repeat_char};
 
 
@@ -310,7 +311,7 @@ fn gen(node: &Node,
         output.push_str("allbox tab(|);\n");
         output.push_str(&scan_format(node, None, None, &mut scan_format_rowspan)?);
     } else if node.name == "th" || node.name == "td" {
-        output.push_str(&format!("T{{\n{}", node.text));
+        output.push_str(&format!("T{{\n{}", escape::cell(&node.text)));
 
         if let Some(rspan) = node.attr.get("rowspan") {
             let rspan = rspan.parse::<usize>().map_err(HtmlError::from_error)?;
@@ -1,6 +1,16 @@
 use std::error::Error;
 use std::iter::repeat;
 
+use regex::Regex;
+
+use ::formatter::entities::decode_entities;
+
+
+lazy_static! {
+    static ref BR: Regex = Regex::new("(?i)<br\\s*/?>").unwrap();
+    static ref TAG: Regex = Regex::new("(?s)<[^>]*>").unwrap();
+}
+
 
 #[derive(Debug)]
 pub enum HtmlError {
@@ -16,3 +26,12 @@ impl HtmlError {
 pub fn repeat_char(c: char, times: usize) -> String {
     repeat(c).take(times).collect::<String>()
 }
+
+/// Text of the contents of a `<pre>` element as shown by a browser: tags
+/// removed, `<br>` as line breaks and character references decoded.
+pub fn pre_text(html: &str) -> String {
+    let text = decode_entities(&TAG.replace_all(&BR.replace_all(html, "\n"), ""));
+
+    // A line break right after `<pre>` is not part of the text
+    if text.starts_with('\n') { text[1..].to_owned() } else { text }
+}
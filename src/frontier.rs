@@ -0,0 +1,200 @@
+//! Crawl state kept in SQLite, so that an interrupted crawl can resume.
+//!
+//! Every crawl is identified by its start URL and has its queue of pages to
+//! fetch with their link depth, the pages already fetched, and the pages that
+//! were skipped along with the reason.
+
+use std::path::Path;
+
+use rusqlite::{self, Connection};
+use url::Url;
+
+use ::errors;
+
+
+const SCHEMA: &'static str = "
+    CREATE TABLE IF NOT EXISTS crawl_state
+        (root TEXT PRIMARY KEY, done INTEGER NOT NULL);
+    CREATE TABLE IF NOT EXISTS crawl_frontier
+        (seq INTEGER PRIMARY KEY AUTOINCREMENT, root TEXT NOT NULL, url TEXT NOT NULL,
+         depth INTEGER NOT NULL, UNIQUE (root, url));
+    CREATE TABLE IF NOT EXISTS crawl_visited
+        (root TEXT NOT NULL, url TEXT NOT NULL, PRIMARY KEY (root, url));
+    CREATE TABLE IF NOT EXISTS crawl_skipped
+        (root TEXT NOT NULL, url TEXT NOT NULL, reason TEXT NOT NULL, PRIMARY KEY (root, url));
+";
+
+
+/// Progress of a crawl found in the database.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum CrawlState {
+    New, InProgress, Done,
+}
+
+
+pub struct Frontier {
+    conn: Connection,
+}
+
+impl Frontier {
+    /// Open the crawl state stored in the database at `path`, which may hold
+    /// other tables too.
+    pub fn open<P: AsRef<Path>>(path: P) -> errors::Result<Frontier> {
+        Frontier::with_connection(Connection::open(path)?)
+    }
+
+    /// Crawl state that is lost when dropped.
+    pub fn in_memory() -> errors::Result<Frontier> {
+        Frontier::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> errors::Result<Frontier> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Frontier { conn: conn })
+    }
+
+    /// Remove all crawl state from the database behind `conn`.
+    pub fn drop_tables(conn: &Connection) -> errors::Result<()> {
+        conn.execute_batch("DROP TABLE IF EXISTS crawl_state;
+                            DROP TABLE IF EXISTS crawl_frontier;
+                            DROP TABLE IF EXISTS crawl_visited;
+                            DROP TABLE IF EXISTS crawl_skipped;")?;
+        Ok(())
+    }
+
+    /// State of the crawl from `root`, which is registered if it's new.
+    pub fn begin(&self, root: &str) -> errors::Result<CrawlState> {
+        let done = self.conn.query_row("SELECT done FROM crawl_state WHERE root = ?",
+                                       &[&root], |row| row.get::<_, i64>(0));
+        match done {
+            Ok(0) => Ok(CrawlState::InProgress),
+            Ok(_) => Ok(CrawlState::Done),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                self.conn.execute("INSERT INTO crawl_state (root, done) VALUES (?, 0)", &[&root])?;
+                Ok(CrawlState::New)
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Mark the crawl from `root` as complete.
+    pub fn finish(&self, root: &str) -> errors::Result<()> {
+        self.conn.execute("UPDATE crawl_state SET done = 1 WHERE root = ?", &[&root])?;
+        self.conn.execute("DELETE FROM crawl_frontier WHERE root = ?", &[&root])?;
+        Ok(())
+    }
+
+    /// Queue `url` unless it was already queued, fetched or skipped.
+    pub fn push(&self, root: &str, url: &Url, depth: usize) -> errors::Result<()> {
+        if self.is_known(root, url)? {
+            return Ok(());
+        }
+
+        self.conn.execute("INSERT OR IGNORE INTO crawl_frontier (root, url, depth) VALUES (?, ?, ?)",
+                          &[&root, &url.as_str(), &(depth as i64)])?;
+        Ok(())
+    }
+
+    /// The page queued first and its depth. It stays queued until it is
+    /// marked as visited or skipped.
+    pub fn next(&self, root: &str) -> errors::Result<Option<(Url, usize)>> {
+        let next = self.conn.query_row(
+            "SELECT url, depth FROM crawl_frontier WHERE root = ? ORDER BY seq LIMIT 1",
+            &[&root], |row| (row.get::<_, String>(0), row.get::<_, i64>(1)));
+
+        match next {
+            Ok((url, depth)) => Ok(Some((Url::parse(&url)?, depth as usize))),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn mark_visited(&self, root: &str, url: &Url) -> errors::Result<()> {
+        self.dequeue(root, url)?;
+        self.conn.execute("INSERT OR IGNORE INTO crawl_visited (root, url) VALUES (?, ?)",
+                          &[&root, &url.as_str()])?;
+        Ok(())
+    }
+
+    pub fn mark_skipped(&self, root: &str, url: &Url, reason: &str) -> errors::Result<()> {
+        self.dequeue(root, url)?;
+        self.conn.execute("INSERT OR IGNORE INTO crawl_skipped (root, url, reason) VALUES (?, ?, ?)",
+                          &[&root, &url.as_str(), &reason])?;
+        Ok(())
+    }
+
+    pub fn is_visited(&self, root: &str, url: &Url) -> errors::Result<bool> {
+        let count = self.conn.query_row("SELECT COUNT(*) FROM crawl_visited WHERE root = ? AND url = ?",
+                                        &[&root, &url.as_str()], |row| row.get::<_, i64>(0))?;
+        Ok(count > 0)
+    }
+
+    pub fn visited_count(&self, root: &str) -> errors::Result<usize> {
+        let count = self.conn.query_row("SELECT COUNT(*) FROM crawl_visited WHERE root = ?",
+                                        &[&root], |row| row.get::<_, i64>(0))?;
+        Ok(count as usize)
+    }
+
+    /// Number of skipped pages by reason.
+    pub fn skipped_counts(&self, root: &str) -> errors::Result<Vec<(String, usize)>> {
+        let mut stmt = self.conn.prepare("SELECT reason, COUNT(*) FROM crawl_skipped \
+                                          WHERE root = ? GROUP BY reason ORDER BY COUNT(*) DESC")?;
+        let counts = stmt.query_map(&[&root], |row| {
+            (row.get::<_, String>(0), row.get::<_, i64>(1) as usize)
+        })?.collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        Ok(counts)
+    }
+
+    fn dequeue(&self, root: &str, url: &Url) -> errors::Result<()> {
+        self.conn.execute("DELETE FROM crawl_frontier WHERE root = ? AND url = ?", &[&root, &url.as_str()])?;
+        Ok(())
+    }
+
+    fn is_known(&self, root: &str, url: &Url) -> errors::Result<bool> {
+        let count = self.conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM crawl_visited WHERE root = ?1 AND url = ?2) + \
+                    (SELECT COUNT(*) FROM crawl_skipped WHERE root = ?1 AND url = ?2)",
+            &[&root, &url.as_str()], |row| row.get::<_, i64>(0))?;
+        Ok(count > 0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use url::Url;
+
+    use super::{CrawlState, Frontier};
+
+    #[test]
+    fn test_resume() {
+        let path = env::temp_dir().join("cppman-rs-test-frontier.db");
+        let _ = fs::remove_file(&path);
+        let root = "http://www.cplusplus.com/reference/";
+        let url = |path: &str| Url::parse(root).unwrap().join(path).unwrap();
+
+        {
+            let frontier = Frontier::open(&path).unwrap();
+            assert_eq!(frontier.begin(root).unwrap(), CrawlState::New);
+            frontier.push(root, &url("a/"), 1).unwrap();
+            frontier.push(root, &url("b/"), 1).unwrap();
+            frontier.push(root, &url("a/"), 2).unwrap();
+            frontier.mark_visited(root, &url("a/")).unwrap();
+            frontier.push(root, &url("a/"), 3).unwrap();
+        }
+
+        let frontier = Frontier::open(&path).unwrap();
+        assert_eq!(frontier.begin(root).unwrap(), CrawlState::InProgress);
+        assert_eq!(frontier.next(root).unwrap(), Some((url("b/"), 1)));
+        frontier.mark_skipped(root, &url("b/"), "disallowed by robots.txt").unwrap();
+        assert_eq!(frontier.next(root).unwrap(), None);
+        assert_eq!(frontier.visited_count(root).unwrap(), 1);
+        assert_eq!(frontier.skipped_counts(root).unwrap(), vec![("disallowed by robots.txt".to_owned(), 1)]);
+
+        frontier.finish(root).unwrap();
+        assert_eq!(frontier.begin(root).unwrap(), CrawlState::Done);
+    }
+}
@@ -0,0 +1,270 @@
+//! HTTP client shared by the crawler and page caching.
+//!
+//! Proxies come from the `Proxy` setting or, failing that, from
+//! `http_proxy`/`HTTPS_PROXY`/`ALL_PROXY`, with hosts in `NO_PROXY` reached
+//! directly. Failed requests are retried with exponential backoff. Redirects
+//! aren't followed, responses to them are returned as they are.
+
+use std::cmp;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use base64;
+use reqwest::{Certificate, Client, ClientBuilder, Proxy, RedirectPolicy, Response, StatusCode};
+use reqwest::header::{Headers, UserAgent};
+use url::Url;
+
+use ::config::Config;
+use ::errors;
+
+
+pub const USER_AGENT: &'static str =
+    concat!("cppman-rs/", env!("CARGO_PKG_VERSION"), " (C++ manual page indexer)");
+
+/// Delay before the first retry in seconds, doubled for every following one.
+const INITIAL_BACKOFF: u64 = 1;
+const MAX_BACKOFF: u64 = 60;
+
+const PEM_BEGIN: &'static str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &'static str = "-----END CERTIFICATE-----";
+
+
+/// Network settings, read from the config and the environment.
+#[derive(Clone, Default)]
+pub struct HttpConfig {
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    /// Proxy used for every request, overriding the environment.
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<PathBuf>,
+    pub retries: u32,
+    /// Don't report retries on stderr, e.g. while a full screen interface
+    /// is drawn.
+    pub quiet: bool,
+}
+
+impl HttpConfig {
+    pub fn from_config(config: &Config) -> HttpConfig {
+        HttpConfig {
+            connect_timeout: config.connect_timeout(),
+            read_timeout: config.read_timeout(),
+            proxy: config.proxy(),
+            ca_bundle: config.ca_bundle(),
+            retries: config.retries(),
+            quiet: false,
+        }
+    }
+}
+
+
+/// Proxies picked from `*_PROXY` environment variables.
+struct EnvProxies {
+    http: Option<Url>,
+    https: Option<Url>,
+    no_proxy: Vec<String>,
+}
+
+impl EnvProxies {
+    fn from_env() -> EnvProxies {
+        let all = env_var(&["ALL_PROXY", "all_proxy"]);
+
+        EnvProxies {
+            // Only the lower case variant, as CGI sets HTTP_PROXY from a request header
+            http: env_var(&["http_proxy"]).or(all.clone()).and_then(|u| Url::parse(&u).ok()),
+            https: env_var(&["HTTPS_PROXY", "https_proxy"]).or(all).and_then(|u| Url::parse(&u).ok()),
+            no_proxy: env_var(&["NO_PROXY", "no_proxy"]).map_or(Vec::new(), |s| parse_no_proxy(&s)),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.http.is_none() && self.https.is_none()
+    }
+
+    fn proxy_for(&self, url: &Url) -> Option<Url> {
+        if url.host_str().map_or(false, |host| bypass_proxy(&self.no_proxy, host)) {
+            return None;
+        }
+
+        match url.scheme() {
+            "http"  => self.http.clone(),
+            "https" => self.https.clone(),
+            _       => None,
+        }
+    }
+}
+
+
+#[derive(Clone)]
+pub struct Http {
+    client: Client,
+    retries: u32,
+    quiet: bool,
+}
+
+impl Http {
+    pub fn new(config: &HttpConfig) -> errors::Result<Http> {
+        let mut builder = ClientBuilder::new()?;
+
+        let mut headers = Headers::new();
+        headers.set(UserAgent::new(USER_AGENT));
+        builder.default_headers(headers);
+        // Followed by `Transport::fetch`, which records them
+        builder.redirect(RedirectPolicy::none());
+
+        // The client has a single timeout for connecting and reading, so the
+        // larger one is used, or the one that isn't disabled
+        let timeout = match (config.connect_timeout, config.read_timeout) {
+            (Some(connect), Some(read)) => Some(cmp::max(connect, read)),
+            (connect, read)             => connect.or(read),
+        };
+        if let Some(timeout) = timeout {
+            builder.timeout(timeout);
+        }
+
+        match config.proxy {
+            Some(ref proxy) => {
+                let proxy = Url::parse(proxy)?;
+                let no_proxy = env_var(&["NO_PROXY", "no_proxy"]).map_or(Vec::new(), |s| parse_no_proxy(&s));
+                builder.proxy(Proxy::custom(move |url| {
+                    match url.host_str() {
+                        Some(host) if bypass_proxy(&no_proxy, host) => None,
+                        _                                           => Some(proxy.clone()),
+                    }
+                }));
+            },
+            None => {
+                let proxies = EnvProxies::from_env();
+                if !proxies.is_empty() {
+                    builder.proxy(Proxy::custom(move |url| proxies.proxy_for(url)));
+                }
+            },
+        }
+
+        if let Some(ref path) = config.ca_bundle {
+            for cert in read_certificates(path)? {
+                builder.add_root_certificate(Certificate::from_der(&cert)?)?;
+            }
+        }
+
+        Ok(Http {
+            client: builder.build()?,
+            retries: config.retries,
+            quiet: config.quiet,
+        })
+    }
+
+    pub fn from_config(config: &Config) -> errors::Result<Http> {
+        Http::new(&HttpConfig::from_config(config))
+    }
+
+    /// GET `url`, retrying with exponential backoff on network errors, server
+    /// errors and rate limiting. The last response is returned whatever its
+    /// status.
+    pub fn get(&self, url: &Url) -> errors::Result<Response> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+
+        loop {
+            let result = self.client.get(url.clone()).send();
+            let transient = match result {
+                Ok(ref res) => is_transient(res.status()),
+                Err(_)      => true,
+            };
+
+            if !transient || attempt >= self.retries {
+                return result.map_err(Into::into);
+            }
+
+            attempt += 1;
+            // Not on stdout, which may be read by an editor or a client
+            if !self.quiet {
+                let _ = writeln!(&mut io::stderr(), "Retrying {} in {}s ({}/{}) ...",
+                                 url, backoff, attempt, self.retries);
+            }
+            thread::sleep(Duration::from_secs(backoff));
+            backoff = cmp::min(backoff * 2, MAX_BACKOFF);
+        }
+    }
+}
+
+
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TooManyRequests
+}
+
+fn env_var(names: &[&str]) -> Option<String> {
+    names.iter().filter_map(|name| env::var(name).ok()).find(|v| !v.is_empty())
+}
+
+fn parse_no_proxy(s: &str) -> Vec<String> {
+    s.split(',').map(|h| h.trim().trim_left_matches('.').to_lowercase()).filter(|h| !h.is_empty()).collect()
+}
+
+/// Whether `host` matches a `NO_PROXY` entry, either exactly or as a
+/// subdomain. `*` matches every host.
+fn bypass_proxy(no_proxy: &[String], host: &str) -> bool {
+    let host = host.to_lowercase();
+    no_proxy.iter().any(|entry| {
+        entry == "*" || host == *entry ||
+            (host.ends_with(entry.as_str()) && host[..host.len() - entry.len()].ends_with('.'))
+    })
+}
+
+/// Read DER certificates from a PEM bundle or a single DER file.
+fn read_certificates(path: &Path) -> errors::Result<Vec<Vec<u8>>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+
+    if !String::from_utf8_lossy(&data).contains(PEM_BEGIN) {
+        return Ok(vec![data]);
+    }
+
+    let text = String::from_utf8_lossy(&data);
+    let mut certs = Vec::new();
+    for block in text.split(PEM_BEGIN).skip(1) {
+        let body = block.split(PEM_END).next().unwrap_or("");
+        let body = body.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+        certs.push(base64::decode(&body).map_err(|e| errors::ErrorKind::Abort(
+            format!("invalid certificate in `{}': {}", path.display(), e)))?);
+    }
+
+    Ok(certs)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::{bypass_proxy, parse_no_proxy, EnvProxies};
+
+    #[test]
+    fn test_no_proxy() {
+        let no_proxy = parse_no_proxy("localhost, .corp.example.com,10.0.0.1");
+
+        assert!(bypass_proxy(&no_proxy, "localhost"));
+        assert!(bypass_proxy(&no_proxy, "corp.example.com"));
+        assert!(bypass_proxy(&no_proxy, "wiki.corp.example.com"));
+        assert!(!bypass_proxy(&no_proxy, "notcorp.example.com"));
+        assert!(!bypass_proxy(&no_proxy, "www.cplusplus.com"));
+        assert!(bypass_proxy(&parse_no_proxy("*"), "www.cplusplus.com"));
+    }
+
+    #[test]
+    fn test_proxy_per_scheme() {
+        let proxies = EnvProxies {
+            http: Some(Url::parse("http://proxy:3128").unwrap()),
+            https: None,
+            no_proxy: parse_no_proxy("en.cppreference.com"),
+        };
+
+        let proxy_for = |url: &str| proxies.proxy_for(&Url::parse(url).unwrap()).map(|u| u.to_string());
+        assert_eq!(proxy_for("http://www.cplusplus.com/reference/"), Some("http://proxy:3128/".to_owned()));
+        assert_eq!(proxy_for("https://www.cplusplus.com/reference/"), None);
+        assert_eq!(proxy_for("http://en.cppreference.com/w/cpp/"), None);
+    }
+}
@@ -0,0 +1,491 @@
+//! Layout of the index database.
+//!
+//! Every source has a table with a row per index name of a page: the name,
+//! the page URL, and what is known about the symbol, its kind, header,
+//! namespace and the standard that introduced it. Class pages that have been
+//! cached also have their members and base classes recorded. The
+//! `schema_version` table holds the version of this layout, and databases
+//! written with an older one, including those of the Python cppman, are
+//! migrated in place when opened.
+
+use std::fmt::{self, Display, Formatter};
+
+use regex::Regex;
+use rusqlite::{self, Connection};
+
+use ::config::{Source, StdVersion};
+use ::errors;
+
+
+/// Version of the layout created by this version of cppman-rs.
+pub const SCHEMA_VERSION: i64 = 3;
+
+const SOURCES: [Source; 2] = [Source::CPlusPlus, Source::CppReference];
+
+/// Namespaces nested in `std`, told apart from classes in qualified names.
+const NAMESPACES: &'static [&'static str] = &[
+    "std", "chrono", "filesystem", "experimental", "placeholders", "rel_ops", "this_thread",
+    "regex_constants", "literals", "chrono_literals", "string_literals", "pmr",
+];
+
+
+lazy_static! {
+    /// Innermost template argument list.
+    static ref TEMPLATE_ARGS: Regex = Regex::new("<[^<>]*>").unwrap();
+}
+
+
+/// Migrations from each version to the next, starting with version 0, with
+/// what they change.
+const MIGRATIONS: [(&'static str, fn(&Connection) -> errors::Result<()>); 3] = [
+    ("names from the Python cppman keywords", migrate_python_keywords),
+    ("symbol kinds, headers, namespaces and standards", migrate_symbol_columns),
+    ("class members and bases", migrate_class_tables),
+];
+
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Kind { Class, Function, Macro, TypeAlias, Concept, Object, Namespace, Header }
+
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&'static str>::into(*self))
+    }
+}
+
+
+impl Kind {
+    pub fn try_from(s: &str) -> errors::Result<Kind> {
+        match s {
+            "class"      => Ok(Kind::Class),
+            "function"   => Ok(Kind::Function),
+            "macro"      => Ok(Kind::Macro),
+            "type alias" => Ok(Kind::TypeAlias),
+            "concept"    => Ok(Kind::Concept),
+            "object"     => Ok(Kind::Object),
+            "namespace"  => Ok(Kind::Namespace),
+            "header"     => Ok(Kind::Header),
+            _            => Err(errors::ErrorKind::ParseKind(s.to_owned()).into()),
+        }
+    }
+}
+
+
+impl Into<&'static str> for Kind {
+    fn into(self) -> &'static str {
+        match self {
+            Kind::Class     => "class",
+            Kind::Function  => "function",
+            Kind::Macro     => "macro",
+            Kind::TypeAlias => "type alias",
+            Kind::Concept   => "concept",
+            Kind::Object    => "object",
+            Kind::Namespace => "namespace",
+            Kind::Header    => "header",
+        }
+    }
+}
+
+
+/// What the formatters find out about a page besides its name.
+#[derive(Clone, Default, PartialEq)]
+pub struct PageInfo {
+    pub kind: Option<Kind>,
+    /// Defining header including the angle brackets, e.g. `<vector>`.
+    pub header: Option<String>,
+    pub std_version: Option<StdVersion>,
+}
+
+
+/// Member of a class as listed on its page.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Member {
+    pub kind: Kind,
+    /// Name without the class, e.g. `push_back`.
+    pub name: String,
+    /// Base class the member is inherited from, `None` for the class's own.
+    pub origin: Option<String>,
+    pub description: String,
+}
+
+/// What a class page tells about the class.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct ClassInfo {
+    pub members: Vec<Member>,
+    /// Classes members are inherited from, in the order the page lists them.
+    /// These include indirect bases.
+    pub bases: Vec<String>,
+}
+
+
+/// Create the tables of the current layout if the database is empty, or
+/// migrate them from an older one.
+pub fn upgrade(conn: &Connection) -> errors::Result<()> {
+    let mut version = match schema_version(conn)? {
+        Some(version) => version,
+        None          => {
+            create_tables(conn)?;
+            1
+        },
+    };
+    check_known(version)?;
+
+    while version < SCHEMA_VERSION {
+        let (changes, migrate) = MIGRATIONS[version as usize];
+        conn.execute_batch("BEGIN")?;
+        let migrated = migrate(conn).and_then(|_| set_schema_version(conn, version + 1));
+        match migrated {
+            Ok(_)  => conn.execute_batch("COMMIT")?,
+            Err(e) => {
+                let _ = conn.execute_batch("ROLLBACK");
+                return Err(errors::ErrorKind::IndexMigrationFailed(
+                    version + 1, changes.to_owned(), e.to_string()).into());
+            },
+        }
+        version += 1;
+    }
+
+    Ok(())
+}
+
+/// Fail unless the database has the current layout, so that it can be used
+/// without `upgrade` writing to it, naming the migration it needs otherwise.
+pub fn check_current(conn: &Connection) -> errors::Result<()> {
+    let version = match schema_version(conn)? {
+        Some(version) => version,
+        None          => return Err(errors::ErrorKind::NoIndexDb.into()),
+    };
+    check_known(version)?;
+
+    if version < SCHEMA_VERSION {
+        let (changes, _) = MIGRATIONS[version as usize];
+        return Err(errors::ErrorKind::IndexNotMigrated(version + 1, changes.to_owned()).into());
+    }
+
+    Ok(())
+}
+
+/// Add an index entry for the page at `url` to the table of `source`.
+pub fn insert(conn: &Connection, source: Source, name: &str, url: &str, info: &PageInfo)
+        -> errors::Result<()> {
+    let kind = info.kind.map(|k| Into::<&'static str>::into(k));
+    let std_version = info.std_version.map(|v| Into::<&'static str>::into(v));
+    let namespace = namespace_of(name, info.kind);
+
+    conn.execute(&format!("INSERT INTO \"{}\" (name, url, kind, header, namespace, std_version) \
+                           VALUES (?, ?, ?, ?, ?, ?)", source),
+                 &[&name, &url, &kind, &info.header, &namespace, &std_version])?;
+    Ok(())
+}
+
+/// Record the members and bases of the class with the index name `class`,
+/// replacing what was recorded before.
+pub fn store_class(conn: &Connection, source: Source, class: &str, info: &ClassInfo)
+        -> errors::Result<()> {
+    let source = source.to_string();
+    for table in &["classes", "members", "bases"] {
+        conn.execute(&format!("DELETE FROM {} WHERE source = ? AND class = ?", table),
+                     &[&source, &class])?;
+    }
+
+    conn.execute("INSERT INTO classes (source, class) VALUES (?, ?)", &[&source, &class])?;
+    for (position, member) in info.members.iter().enumerate() {
+        let kind: &'static str = member.kind.into();
+        conn.execute("INSERT INTO members (source, class, position, kind, name, origin, description) \
+                      VALUES (?, ?, ?, ?, ?, ?, ?)",
+                     &[&source, &class, &(position as i64), &kind, &member.name, &member.origin,
+                       &member.description])?;
+    }
+    for (position, base) in info.bases.iter().enumerate() {
+        conn.execute("INSERT INTO bases (source, class, position, base) VALUES (?, ?, ?, ?)",
+                     &[&source, &class, &(position as i64), base])?;
+    }
+
+    Ok(())
+}
+
+/// Members and bases recorded for the class with the index name `class`,
+/// `None` if its page hasn't been looked at yet.
+pub fn load_class(conn: &Connection, source: Source, class: &str) -> errors::Result<Option<ClassInfo>> {
+    let source = source.to_string();
+    let count = conn.query_row("SELECT COUNT(*) FROM classes WHERE source = ? AND class = ?",
+                               &[&source, &class], |row| row.get::<_, i64>(0))?;
+    if count == 0 {
+        return Ok(None);
+    }
+
+    let mut stmt = conn.prepare("SELECT kind, name, origin, description FROM members \
+                                 WHERE source = ? AND class = ? ORDER BY position")?;
+    let rows = stmt.query_map(&[&source, &class], |row| {
+        (row.get::<_, String>(0), row.get::<_, String>(1), row.get::<_, Option<String>>(2),
+         row.get::<_, String>(3))
+    })?.collect::<Result<Vec<_>, rusqlite::Error>>()?;
+
+    let mut members = Vec::new();
+    for (kind, name, origin, description) in rows {
+        members.push(Member {
+            kind: Kind::try_from(&kind)?,
+            name: name,
+            origin: origin,
+            description: description,
+        });
+    }
+
+    let mut stmt = conn.prepare("SELECT base FROM bases WHERE source = ? AND class = ? ORDER BY position")?;
+    let bases = stmt.query_map(&[&source, &class], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+
+    Ok(Some(ClassInfo { members: members, bases: bases }))
+}
+
+/// Namespace of the symbol called `name`, the leading components that are
+/// known namespaces. Names without one are in `std`, except macros.
+pub fn namespace_of(name: &str, kind: Option<Kind>) -> Option<String> {
+    if kind == Some(Kind::Macro) || kind == Some(Kind::Header) {
+        return None;
+    }
+
+    // Template arguments may contain `::` too
+    let mut plain = name.to_owned();
+    while TEMPLATE_ARGS.is_match(&plain) {
+        plain = TEMPLATE_ARGS.replace_all(&plain, "").into_owned();
+    }
+
+    let parts = plain.split("::").collect::<Vec<_>>();
+    let mut namespace = parts[..parts.len() - 1].iter()
+        .take_while(|p| NAMESPACES.contains(&p.trim()))
+        .map(|p| p.trim())
+        .collect::<Vec<_>>();
+
+    if namespace.first() != Some(&"std") {
+        namespace.insert(0, "std");
+    }
+
+    Some(namespace.join("::"))
+}
+
+
+/// Fail if the layout `version` is newer than the ones we know.
+fn check_known(version: i64) -> errors::Result<()> {
+    if version > SCHEMA_VERSION {
+        return Err(errors::ErrorKind::Abort(format!(
+            "the index database has schema version {}, this version of cppman-rs only knows up to {}; \
+             run `cppman-rs index rebuild`", version, SCHEMA_VERSION)).into());
+    }
+
+    Ok(())
+}
+
+/// Version of the layout of the database, `None` if it has no index tables.
+fn schema_version(conn: &Connection) -> errors::Result<Option<i64>> {
+    if has_table(conn, "schema_version")? {
+        let version = conn.query_row("SELECT MAX(version) FROM schema_version", &[],
+                                     |row| row.get::<_, Option<i64>>(0))?;
+        if let Some(version) = version {
+            return Ok(Some(version));
+        }
+    }
+
+    for &source in &SOURCES {
+        let table = source.to_string();
+        if has_table(conn, &table)? {
+            // The Python cppman keeps titles and their other names apart
+            let version = if columns(conn, &table)?.iter().any(|c| c == "title") { 0 } else { 1 };
+            return Ok(Some(version));
+        }
+    }
+
+    Ok(None)
+}
+
+fn set_schema_version(conn: &Connection, version: i64) -> errors::Result<()> {
+    conn.execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL);
+                        DELETE FROM schema_version;")?;
+    conn.execute("INSERT INTO schema_version (version) VALUES (?)", &[&version])?;
+    Ok(())
+}
+
+/// Tables of version 1, from which the current layout is migrated.
+fn create_tables(conn: &Connection) -> errors::Result<()> {
+    for &source in &SOURCES {
+        conn.execute(&format!("CREATE TABLE IF NOT EXISTS \"{}\" \
+                               (name VARCHAR(255), url VARCHAR(255))", source), &[])?;
+    }
+    Ok(())
+}
+
+fn has_table(conn: &Connection, table: &str) -> errors::Result<bool> {
+    let count = conn.query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+                               &[&table], |row| row.get::<_, i64>(0))?;
+    Ok(count > 0)
+}
+
+fn columns(conn: &Connection, table: &str) -> errors::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table))?;
+    let columns = stmt.query_map(&[], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+    Ok(columns)
+}
+
+
+/// 0 to 1: The Python cppman stores `(id, title, url)` and the other names
+/// of a page in `<source>_keywords`, which become rows of their own.
+fn migrate_python_keywords(conn: &Connection) -> errors::Result<()> {
+    for &source in &SOURCES {
+        let table = source.to_string();
+        if !has_table(conn, &table)? {
+            continue;
+        }
+
+        conn.execute_batch(&format!("
+            ALTER TABLE \"{0}\" RENAME TO \"{0}_python\";
+            CREATE TABLE \"{0}\" (name VARCHAR(255), url VARCHAR(255));
+            INSERT INTO \"{0}\" (name, url) SELECT title, url FROM \"{0}_python\";", table))?;
+
+        let keywords = format!("{}_keywords", table);
+        if has_table(conn, &keywords)? {
+            conn.execute_batch(&format!("
+                INSERT INTO \"{0}\" (name, url)
+                    SELECT k.keyword, p.url FROM \"{1}\" k JOIN \"{0}_python\" p ON k.id = p.id
+                    WHERE k.keyword != p.title;
+                DROP TABLE \"{1}\";", table, keywords))?;
+        }
+
+        conn.execute_batch(&format!("DROP TABLE \"{}_python\";", table))?;
+    }
+
+    create_tables(conn)
+}
+
+/// 1 to 2: Symbol metadata. Only the namespace can be told from the name,
+/// the rest is filled in by the next `index rebuild`.
+fn migrate_symbol_columns(conn: &Connection) -> errors::Result<()> {
+    for &source in &SOURCES {
+        let table = source.to_string();
+        conn.execute_batch(&format!("
+            ALTER TABLE \"{0}\" ADD COLUMN kind TEXT;
+            ALTER TABLE \"{0}\" ADD COLUMN header TEXT;
+            ALTER TABLE \"{0}\" ADD COLUMN namespace TEXT;
+            ALTER TABLE \"{0}\" ADD COLUMN std_version TEXT;", table))?;
+
+        let mut stmt = conn.prepare(&format!("SELECT DISTINCT name FROM \"{}\"", table))?;
+        let names = stmt.query_map(&[], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+        for name in names {
+            conn.execute(&format!("UPDATE \"{}\" SET namespace = ? WHERE name = ?", table),
+                         &[&namespace_of(&name, None), &name])?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 2 to 3: Members and bases of classes, recorded as their pages are cached.
+/// `classes` tells classes without either from pages not looked at yet.
+fn migrate_class_tables(conn: &Connection) -> errors::Result<()> {
+    conn.execute_batch("
+        CREATE TABLE classes (source TEXT NOT NULL, class TEXT NOT NULL, PRIMARY KEY (source, class));
+        CREATE TABLE members
+            (source TEXT NOT NULL, class TEXT NOT NULL, position INTEGER NOT NULL,
+             kind TEXT NOT NULL, name TEXT NOT NULL, origin TEXT, description TEXT NOT NULL);
+        CREATE TABLE bases
+            (source TEXT NOT NULL, class TEXT NOT NULL, position INTEGER NOT NULL, base TEXT NOT NULL);")?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rusqlite::Connection;
+
+    use super::{columns, insert, load_class, namespace_of, schema_version, store_class, upgrade,
+                ClassInfo, Kind, Member, PageInfo, SCHEMA_VERSION};
+    use ::config::{Source, StdVersion};
+
+    #[test]
+    fn test_namespace() {
+        assert_eq!(namespace_of("vector::push_back", Some(Kind::Function)), Some("std".to_owned()));
+        assert_eq!(namespace_of("std::chrono::duration<Rep,Period>::count", None),
+                   Some("std::chrono".to_owned()));
+        assert_eq!(namespace_of("rel_ops::operator!=", None), Some("std::rel_ops".to_owned()));
+        assert_eq!(namespace_of("assert", Some(Kind::Macro)), None);
+    }
+
+    #[test]
+    fn test_upgrade_rust_v1() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE \"cplusplus.com\" (name VARCHAR(255), url VARCHAR(255));
+                            INSERT INTO \"cplusplus.com\" VALUES ('chrono::duration', 'u1');").unwrap();
+
+        upgrade(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), Some(SCHEMA_VERSION));
+        assert_eq!(columns(&conn, "cppreference.com").unwrap(),
+                   vec!["name", "url", "kind", "header", "namespace", "std_version"]);
+        let namespace: String = conn.query_row("SELECT namespace FROM \"cplusplus.com\"", &[],
+                                               |row| row.get(0)).unwrap();
+        assert_eq!(namespace, "std::chrono");
+
+        // Upgrading again is a no-op
+        upgrade(&conn).unwrap();
+        insert(&conn, Source::CPlusPlus, "vector", "u2", &PageInfo {
+            kind: Some(Kind::Class),
+            header: Some("<vector>".to_owned()),
+            std_version: Some(StdVersion::Cpp98),
+        }).unwrap();
+        let row: (String, String, String) = conn.query_row(
+            "SELECT kind, header, std_version FROM \"cplusplus.com\" WHERE name = 'vector'", &[],
+            |row| (row.get(0), row.get(1), row.get(2))).unwrap();
+        assert_eq!(row, ("class".to_owned(), "<vector>".to_owned(), "c++98".to_owned()));
+    }
+
+    #[test]
+    fn test_upgrade_python() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            CREATE TABLE \"cplusplus.com\" (id INTEGER PRIMARY KEY, title VARCHAR(255), url VARCHAR(255));
+            CREATE TABLE \"cplusplus.com_keywords\" (id INTEGER NOT NULL, keyword VARCHAR(255));
+            INSERT INTO \"cplusplus.com\" VALUES (1, 'vector::operator==', 'u1');
+            INSERT INTO \"cplusplus.com_keywords\" VALUES (1, 'vector::operator==');
+            INSERT INTO \"cplusplus.com_keywords\" VALUES (1, 'vector::operator!=');").unwrap();
+
+        upgrade(&conn).unwrap();
+        let mut stmt = conn.prepare("SELECT name, url FROM \"cplusplus.com\" ORDER BY name").unwrap();
+        let rows = stmt.query_map(&[], |row| (row.get::<_, String>(0), row.get::<_, String>(1))).unwrap()
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rows, vec![("vector::operator!=".to_owned(), "u1".to_owned()),
+                              ("vector::operator==".to_owned(), "u1".to_owned())]);
+        assert_eq!(schema_version(&conn).unwrap(), Some(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn test_classes() {
+        let conn = Connection::open_in_memory().unwrap();
+        upgrade(&conn).unwrap();
+        assert_eq!(load_class(&conn, Source::CPlusPlus, "istream").unwrap(), None);
+
+        let info = ClassInfo {
+            members: vec![
+                Member {
+                    kind: Kind::Function,
+                    name: "gcount".to_owned(),
+                    origin: None,
+                    description: "Get character count (public member function)".to_owned(),
+                },
+                Member {
+                    kind: Kind::Function,
+                    name: "flags".to_owned(),
+                    origin: Some("ios_base".to_owned()),
+                    description: "Get/set format flags (public member function)".to_owned(),
+                },
+            ],
+            bases: vec!["ios".to_owned(), "ios_base".to_owned()],
+        };
+        store_class(&conn, Source::CPlusPlus, "istream", &ClassInfo::default()).unwrap();
+        assert_eq!(load_class(&conn, Source::CPlusPlus, "istream").unwrap(), Some(ClassInfo::default()));
+
+        store_class(&conn, Source::CPlusPlus, "istream", &info).unwrap();
+        assert_eq!(load_class(&conn, Source::CPlusPlus, "istream").unwrap(), Some(info));
+        assert_eq!(load_class(&conn, Source::CppReference, "istream").unwrap(), None);
+    }
+}
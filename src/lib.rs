@@ -0,0 +1,77 @@
+//! Rust port of [cppman](https://github.com/aitjcize/cppman), C++ 98/11/14
+//! manual pages for Linux/MacOS.
+//!
+//! The `cppman-rs` binary is a thin command line interface over this crate.
+//! Embedders create an `Environ` from explicit `Paths`, so that nothing under
+//! the real home directory is touched, and drive everything through `Cppman`:
+//!
+//! ```no_run
+//! use cppman_rs::{Cppman, Environ, Paths};
+//!
+//! let env = Environ::from_paths(Paths::under("/tmp/cppman-rs")).unwrap();
+//! let cm = Cppman::new_default(&env);
+//! let (name, url) = cm.lookup("vector::emplace_back").unwrap();
+//! ```
+//!
+//! Formatting is available separately through `html2groff` and `groff2man`.
+
+#![recursion_limit = "128"]
+
+extern crate either;
+extern crate ini;
+extern crate regex;
+#[macro_use]
+extern crate lazy_static;
+extern crate chrono;
+extern crate select;
+#[macro_use]
+extern crate nom;
+extern crate rusqlite;
+extern crate isatty;
+extern crate flate2;
+extern crate reqwest;
+extern crate url;
+extern crate ordermap;
+extern crate term_size;
+#[macro_use]
+extern crate error_chain;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate termion;
+extern crate base64;
+extern crate encoding_rs;
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck;
+
+pub mod completion;
+pub mod config;
+pub mod cppman;
+mod crawler;
+mod embedded;
+pub mod environ;
+pub mod errors;
+pub mod example;
+pub mod formatter;
+mod frontier;
+pub mod http;
+pub mod index;
+pub mod mandb;
+pub mod output;
+mod robots;
+pub mod server;
+pub mod transport;
+pub mod tui;
+mod util;
+pub mod vim;
+
+pub use config::{Charset, Config, Colour, ManSection, Pager, Source, StdVersion};
+pub use cppman::Cppman;
+pub use environ::{Environ, Overrides, Paths};
+pub use errors::{Error, ErrorKind, Result};
+pub use formatter::html2groff;
+pub use output::{OutputFormat, PageEntry};
+pub use util::{groff2man, groff2man_charset, html2man};
@@ -1,190 +1,247 @@
-#![recursion_limit = "128"]
-
-extern crate either;
-extern crate ini;
-extern crate regex;
-#[macro_use]
-extern crate lazy_static;
-extern crate chrono;
-extern crate select;
-#[macro_use]
-extern crate nom;
-extern crate rusqlite;
-extern crate isatty;
-extern crate flate2;
-extern crate reqwest;
-extern crate url;
-extern crate ordermap;
-#[macro_use]
-extern crate mime;
 #[macro_use]
 extern crate clap;
-extern crate term_size;
-#[macro_use]
-extern crate error_chain;
+extern crate cppman_rs;
 
-mod config;
-mod cppman;
-mod crawler;
-mod environ;
-mod errors;
-mod formatter;
-mod util;
+mod cli;
 
 use std::io::{self, Write, BufRead};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
 
-use clap::{App, Arg};
+use clap::ArgMatches;
+
+use cppman_rs::{completion, errors, mandb, vim, Cppman, Environ, Overrides, OutputFormat};
+use cppman_rs::config::KEYS;
+use cppman_rs::errors::ErrorKind;
+use cppman_rs::formatter::BRIEF_SECTIONS;
+use cppman_rs::transport::FetchMode;
+
+
+/// Handle `config get|set|list|reset`.
+fn run_config(env: &Environ, matches: &ArgMatches) -> errors::Result<()> {
+    match matches.subcommand() {
+        ("get", Some(m)) => {
+            println!("{}", env.config.get(m.value_of("key").unwrap())?);
+        },
+        ("set", Some(m)) => {
+            let (key, value) = (m.value_of("key").unwrap(), m.value_of("value").unwrap());
+            env.config.set(key, value)?;
+            println!("{} set to `{}'.", key, value);
+
+            // Registered pages follow the source
+            if key == "Source" {
+                mandb::update(env, true)?;
+            }
+        },
+        ("list", _) => {
+            for (key, value) in env.config.list() {
+                println!("{} = {}", key.name, value);
+            }
+        },
+        ("reset", Some(m)) => {
+            if let Some(key) = m.value_of("key") {
+                env.config.reset(Some(key))?;
+                println!("{} reset to `{}'.", key, env.config.get(key)?);
+            } else {
+                env.config.reset(None)?;
+                println!("All settings reset to defaults.");
+            }
+        },
+        _ => {
+            let keys = KEYS.iter().map(|k| format!("    {:<16}{}", k.name, k.help)).collect::<Vec<_>>();
+            return Err(ErrorKind::Usage(format!(
+                "usage: cppman-rs config get KEY | set KEY VALUE | list | reset [KEY]\n\n\
+                 Available keys:\n{}", keys.join("\n"))).into());
+        },
+    }
 
-use ::config::{Source, Pager};
-use ::cppman::Cppman;
-use ::environ::Environ;
+    Ok(())
+}
 
+/// Handle `vim-plugin install|print`.
+fn run_vim_plugin(env: &Environ, matches: &ArgMatches) -> errors::Result<()> {
+    match matches.subcommand() {
+        ("install", Some(m)) => {
+            let dir = match m.value_of("dir") {
+                Some(dir) => PathBuf::from(dir),
+                None      => vim::package_dir(env, vim::Editor::try_from(m.value_of("editor").unwrap())?),
+            };
+
+            for path in vim::install(&dir)? {
+                println!("Installed {}", path.display());
+            }
+        },
+        ("print", _) => {
+            // The script loads the plugin from the data directory
+            let dir = env.data_dir.join("vim");
+            vim::install(&dir)?;
+            print!("{}", vim::standalone_config(&dir));
+        },
+        _ => unreachable!(),
+    }
 
-pub fn get_lib_path(s: &str) -> PathBuf {
-    PathBuf::from(s)
+    Ok(())
 }
 
+/// Handle `mandb on|off|status`.
+fn run_mandb(env: &Environ, state: &str) -> errors::Result<()> {
+    match state {
+        "on"  => {
+            mandb::enable(env)?;
+            println!("{} registered with mandb.", env.man_dir.display());
+        },
+        "off" => {
+            mandb::disable(env)?;
+            println!("{} removed from mandb.", env.man_dir.display());
+        },
+        _     => {
+            let status = mandb::status(env)?;
+            println!("UpdateManPath: {}", status.enabled);
+            println!("~/.manpath entry: {}", if status.registered { "present" } else { "missing" });
+            println!("man3: {}", status.man3.map_or("not linked".to_owned(), |p| p.display().to_string()));
+        },
+    }
 
-fn main() {
-    let matches = App::new("cppman-rs")
-        .version(crate_version!())
-        .about("Rust port of cppman, originally written in Python")
-        .arg(Arg::with_name("source")
-                 .help(&format!("Select source, either 'cppreference.com' or \
-                                 'cplusplus.com'. [default: {}]",
-                                 Into::<&'static str>::into(Source::default())))
-                 .short("s")
-                 .long("source")
-                 .takes_value(true))
-        .arg(Arg::with_name("cache-all")
-                 .help("Cache all available man pages from cppreference.com \
-                        and cplusplus.com to enable offline browsing.")
-                 .short("c")
-                 .long("cache-all"))
-        .arg(Arg::with_name("clear-cache")
-                 .help("Clear all cached files.")
-                 .short("C")
-                 .long("clear-cache"))
-        .arg(Arg::with_name("find-page")
-                 .help("Find man page.")
-                 .short("f")
-                 .long("find-page")
-                 .takes_value(true))
-        .arg(Arg::with_name("force-update")
-                 .help("Force cppman-rs to update existing cache when \
-                        '--cache-all' or browsing man pages that were already \
-                        cached.")
-                 .short("o")
-                 .long("force-update"))
-        .arg(Arg::with_name("use-mandb")
-                 .help("If provided, cppman-rs adds \
-                        manpage path to mandb so that you can view C++ manpages \
-                        with `man' command.")
-                 .short("m")
-                 .long("use-mandb"))
-        .arg(Arg::with_name("pager")
-                 .help(&format!("Select pager to use, accepts 'vim', 'less' or \
-                                'system'. 'system' uses $PAGER environment as pager. \
-                                [default: {}]", Into::<&'static str>::into(Pager::default())))
-                 .short("p")
-                 .long("pager")
-                 .takes_value(true))
-        .arg(Arg::with_name("rebuild-index")
-                 .help("rebuild index database for the selected source, \
-                        either 'cppreference.com' or 'cplusplus.com'.")
-                 .short("r")
-                 .long("rebuild-index"))
-        .arg(Arg::with_name("force-columns")
-                 .help("Force terminal columns.")
-                 .long("force-columns"))
-        .arg(Arg::with_name("manpage")
-                 .multiple(true)
-                 .help("Requested manpages"))
-        .get_matches();
-
-    let source = matches.value_of("source");
-    let cache_all = matches.is_present("cache-all");
-    let clear_cache = matches.is_present("clear-cache");
-    let find_page = matches.value_of("find-page");
+    Ok(())
+}
+
+/// Handle `view`, asking before each page after the first one.
+fn run_view(env: &Environ, matches: &ArgMatches) -> errors::Result<()> {
     let force_update = matches.is_present("force-update");
-    let use_mandb = matches.occurrences_of("use-mandb");
-    let pager = matches.value_of("pager");
-    let rebuild_index = matches.is_present("rebuild-index");
-    let force_columns = value_t!(matches, "force-columns", usize).ok();
-    let manpage = matches.values_of("manpage");
+    let force_columns = match matches.value_of("force-columns") {
+        Some(c) => Some(c.parse::<usize>().map_err(|_| ErrorKind::Usage(
+            format!("Invalid value `{}' for option `--force-columns'", c)))?),
+        None    => None,
+    };
 
-    let env = Environ::new();
+    let manpages = matches.values_of("manpage")
+        .ok_or(ErrorKind::Usage("What manual page do you want?".to_owned()))?;
 
-    if cache_all {
-        let cm = Cppman::new(Some(force_update), None, &env);
-        let _ = cm.cache_all().expect("Error while caching manpages");
-    }
+    let sections = if matches.is_present("brief") {
+        Some(BRIEF_SECTIONS.iter().map(|s| s.to_string()).collect())
+    } else {
+        matches.values_of("section").map(|v| v.map(str::to_owned).collect())
+    };
 
-    if clear_cache {
-        let cm = Cppman::new_default(&env);
-        let _ = cm.clear_cache().expect("Error while clearing the cache");
-    }
+    let mut cm = Cppman::new(Some(force_update), force_columns, env);
+    cm.set_sections(sections);
 
-    if find_page.is_some() {
-        let cm = Cppman::new_default(&env);
-        let _ = cm.find(find_page.unwrap()).expect("Error while finding a page");
-    }
-
-    if let Some(source) = source {
-        if let Ok(source) = Source::try_from(source) {
-            env.config.set_source(source);
-            println!("Source set to `{}'.", source);
-        } else {
-            writeln!(&mut io::stderr(), "Invalid value `{}' for option `--source'", source)
-                .expect("Failed printing to stderr");
-            process::exit(1);
+    for (i, arg) in manpages.enumerate() {
+        if i > 0 {
+            println!("--CppMan-- next: {}(3) [ view (return) | skip (Ctrl-D) \
+                      | quit (Ctrl-C) ]", arg);
+            let stdin = io::stdin();
+            // Ignore the actual input, we only need a user's Enter
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                continue;
+            }
         }
-    }
 
-    if let Some(pager) = pager {
-        if let Ok(pager) = Pager::try_from(pager) {
-            env.config.set_pager(pager);
-            println!("Pager set to `{}'.", pager);
-        } else {
-            writeln!(&mut io::stderr(), "Invalid value `{}' for option `--pager'", pager)
-                .expect("Failed printing to stderr");
-            process::exit(1);
-        }
+        cm.man(arg)?;
     }
 
-    if use_mandb > 0 {
-        if !env.config.update_man_path() {
-            env.config.set_update_man_path(true);
-        }
-        if env.config.update_man_path() {
-            util::update_mandb_path(&env).expect("Cannot update mandb path");
-            util::update_man3_link(&env).expect("Cannot update man3 link");
-        }
-    }
+    Ok(())
+}
 
-    if rebuild_index {
-        let cm = Cppman::new_default(&env);
-        cm.rebuild_index();
+fn run(matches: &ArgMatches) -> errors::Result<()> {
+    let overrides = Overrides {
+        config_dir: matches.value_of("config-dir").map(PathBuf::from),
+        data_dir: matches.value_of("data-dir").map(PathBuf::from),
+        cache_dir: matches.value_of("cache-dir").map(PathBuf::from),
+        man_dir: matches.value_of("man-dir").map(PathBuf::from),
+    };
+    let mut env = Environ::with_overrides(overrides.with_env())?;
+
+    if let Some(dir) = matches.value_of("record") {
+        env.fetch = FetchMode::Record(PathBuf::from(dir));
+    } else if let Some(dir) = matches.value_of("replay") {
+        env.fetch = FetchMode::Replay(PathBuf::from(dir));
     }
 
-    if manpage.is_none() {
-        writeln!(&mut io::stderr(), "What manual page do you want?").expect("Failed printing to stderr");
-        process::exit(1);
+    match matches.subcommand() {
+        ("view", Some(m)) => run_view(&env, m),
+        ("find", Some(m)) => {
+            let format = OutputFormat::try_from(m.value_of("format").unwrap())?;
+            Cppman::new_default(&env).find(m.value_of("pattern").unwrap(), format)
+        },
+        ("lookup", Some(m)) => {
+            let format = OutputFormat::try_from(m.value_of("format").unwrap())?;
+            Cppman::new_default(&env).lookup_all(m.values_of("manpage").unwrap(), format)
+        },
+        ("header", Some(m)) => Cppman::new_default(&env).list_header(m.value_of("header").unwrap()),
+        ("which-header", Some(m)) => Cppman::new_default(&env).print_which_header(m.value_of("name").unwrap()),
+        ("members", Some(m)) => {
+            Cppman::new_default(&env).print_members(m.value_of("class").unwrap(), m.is_present("hierarchy"))
+        },
+        ("example", Some(m)) => {
+            let out_dir = m.value_of("out").map(Path::new);
+            Cppman::new_default(&env).run_example(m.value_of("page").unwrap(), out_dir, m.is_present("run"))
+        },
+        ("cache", Some(m)) => match m.subcommand() {
+            ("all", Some(m)) => {
+                Cppman::new(Some(m.is_present("force-update")), None, &env).cache_all()
+            },
+            ("clear", _)     => Cppman::new_default(&env).clear_cache(),
+            ("reformat", _)  => Cppman::new_default(&env).reformat(),
+            _                => unreachable!(),
+        },
+        ("index", Some(m)) => match m.subcommand() {
+            ("rebuild", Some(m)) => Cppman::new_default(&env).rebuild_index(m.is_present("restart")),
+            _                    => unreachable!(),
+        },
+        ("config", Some(m)) => run_config(&env, m),
+        ("browse", _) => {
+            // Anything written to stderr would end up over the browser
+            let mut cm = Cppman::new_default(&env);
+            cm.set_quiet(true);
+            cppman_rs::tui::browse(&cm)
+        },
+        ("serve", _) => cppman_rs::server::serve(&Cppman::new_default(&env)),
+        ("vim-plugin", Some(m)) => run_vim_plugin(&env, m),
+        ("completions", Some(m)) => {
+            print!("{}", completion::script(completion::Shell::try_from(m.value_of("shell").unwrap())?));
+            Ok(())
+        },
+        ("complete-page", Some(m)) => {
+            for name in Cppman::new_default(&env).complete(m.value_of("prefix").unwrap())? {
+                println!("{}", name);
+            }
+            Ok(())
+        },
+        ("mandb", Some(m)) => run_mandb(&env, m.value_of("state").unwrap()),
+        _ => run_view(&env, matches),
     }
+}
 
-    let cm = Cppman::new(Some(force_update), force_columns, &env);
+/// Print an error with its causes to stderr.
+fn report(e: &errors::Error) {
+    let stderr = &mut io::stderr();
 
-    for (i, arg) in manpage.unwrap().enumerate() {
-        if i > 0 {
-            println!("--CppMan-- next: {}(3) [ view (return) | skip (Ctrl-D) \
-                      | quit (Ctrl-C) ]", arg);
-            let stdin = io::stdin();
-            // Ignore the actual input, we only need a user's Enter
-            let _ = stdin.lock().lines().next().expect("Cannot read a line from stdin");
-        }
+    let _ = writeln!(stderr, "cppman-rs: {}", e);
+    for cause in e.iter().skip(1) {
+        let _ = writeln!(stderr, "  caused by: {}", cause);
+    }
+}
 
-        cm.man(arg).expect("Error while printing the manpage");
+
+fn main() {
+    let matches = match cli::build_cli().get_matches_safe() {
+        Ok(matches) => matches,
+        Err(e) => {
+            if e.use_stderr() {
+                let _ = writeln!(&mut io::stderr(), "{}", e.message);
+                process::exit(errors::EXIT_USAGE);
+            }
+
+            // --help and --version
+            println!("{}", e.message);
+            process::exit(0);
+        },
+    };
+
+    if let Err(e) = run(&matches) {
+        report(&e);
+        process::exit(e.exit_code());
     }
 }
@@ -0,0 +1,309 @@
+//! Registration of the man page cache with man-db.
+//!
+//! Enabling adds a `MANDATORY_MANPATH` line for the man dir to `~/.manpath`,
+//! points `man3` at the pages of the configured source and indexes the man
+//! dir with `mandb`. Disabling undoes all three; both are idempotent.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use ::config::Source;
+use ::environ::Environ;
+use ::errors;
+use ::util::create_file_symlink;
+
+
+const MANPATH_DIRECTIVE: &'static str = "MANDATORY_MANPATH";
+
+/// Databases `mandb` creates in the man dir.
+const MANDB_FILES: &'static [&'static str] = &["index.db", "index.bt", "index.dir", "index.pag"];
+
+
+pub struct Status {
+    /// Value of the `UpdateManPath` setting.
+    pub enabled: bool,
+    /// Whether `~/.manpath` lists the man dir.
+    pub registered: bool,
+    /// Target of the `man3` link, if it is one.
+    pub man3: Option<PathBuf>,
+}
+
+
+fn manpath_file(home: &Path) -> PathBuf {
+    home.join(".manpath")
+}
+
+/// Whether `line` is the entry for `man_dir`. Other lines mentioning it, e.g.
+/// in comments or `MANPATH_MAP`, are left alone.
+fn is_entry(line: &str, man_dir: &Path) -> bool {
+    let mut words = line.split_whitespace();
+    words.next() == Some(MANPATH_DIRECTIVE) && words.next().map(Path::new) == Some(man_dir) &&
+        words.next().is_none()
+}
+
+fn read_lines(path: &Path) -> errors::Result<Vec<String>> {
+    match File::open(path) {
+        Ok(f)  => BufReader::new(f).lines().collect::<io::Result<_>>().map_err(Into::into),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_lines(path: &Path, lines: &[String]) -> errors::Result<()> {
+    let mut f = File::create(path)?;
+    for line in lines {
+        writeln!(f, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Whether `~/.manpath` under `home` lists `man_dir`.
+pub fn has_manpath(home: &Path, man_dir: &Path) -> errors::Result<bool> {
+    Ok(read_lines(&manpath_file(home))?.iter().any(|l| is_entry(l, man_dir)))
+}
+
+/// Add `man_dir` to `~/.manpath` under `home`, returning whether the file
+/// changed.
+pub fn add_manpath(home: &Path, man_dir: &Path) -> errors::Result<bool> {
+    let path = manpath_file(home);
+    let mut lines = read_lines(&path)?;
+
+    if lines.iter().any(|l| is_entry(l, man_dir)) {
+        return Ok(false);
+    }
+
+    lines.push(format!("{}\t{}", MANPATH_DIRECTIVE, man_dir.display()));
+    write_lines(&path, &lines)?;
+    Ok(true)
+}
+
+/// Remove `man_dir` from `~/.manpath` under `home`, keeping every other line,
+/// and return whether the file changed. The file is deleted if nothing is
+/// left in it.
+pub fn remove_manpath(home: &Path, man_dir: &Path) -> errors::Result<bool> {
+    let path = manpath_file(home);
+    let lines = read_lines(&path)?;
+    let kept = lines.iter().filter(|l| !is_entry(l, man_dir)).cloned().collect::<Vec<_>>();
+
+    if kept.len() == lines.len() {
+        return Ok(false);
+    }
+
+    if kept.iter().all(|l| l.trim().is_empty()) {
+        fs::remove_file(&path)?;
+    } else {
+        write_lines(&path, &kept)?;
+    }
+    Ok(true)
+}
+
+/// Point `man_dir/man3` at the pages of `source`.
+///
+/// An existing link is replaced and an empty directory removed. Anything
+/// else at `man3` may be the user's own pages, so it's reported instead.
+pub fn link_man3(man_dir: &Path, source: Source) -> errors::Result<()> {
+    let man3 = man_dir.join("man3");
+    let target = PathBuf::from(source.to_string());
+
+    fs::create_dir_all(man_dir.join(&target))?;
+
+    match fs::symlink_metadata(&man3) {
+        Ok(ref m) if m.file_type().is_symlink() => {
+            if fs::read_link(&man3)? == target {
+                return Ok(());
+            }
+            fs::remove_file(&man3)?;
+        },
+        Ok(ref m) if m.is_dir() && fs::read_dir(&man3)?.next().is_none() => {
+            fs::remove_dir(&man3)?;
+        },
+        Ok(_) => {
+            return Err(errors::ErrorKind::Abort(format!(
+                "can't link `{}' to {}: it already exists, move it away first",
+                man3.display(), target.display())).into());
+        },
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+        Err(e) => return Err(e.into()),
+    }
+
+    create_file_symlink(target, &man3)
+}
+
+/// Remove the `man3` link, returning whether there was one. Directories are
+/// never touched.
+pub fn unlink_man3(man_dir: &Path) -> errors::Result<bool> {
+    let man3 = man_dir.join("man3");
+
+    match fs::symlink_metadata(&man3) {
+        Ok(ref m) if m.file_type().is_symlink() => {
+            fs::remove_file(&man3)?;
+            Ok(true)
+        },
+        _ => Ok(false),
+    }
+}
+
+/// Index `man_dir`, and only it, with `mandb`.
+pub fn run_mandb(man_dir: &Path, quiet: bool) -> errors::Result<()> {
+    let mut cmd = Command::new("mandb");
+    if quiet {
+        cmd.arg("-q");
+    }
+
+    let status = cmd.arg(man_dir).status().map_err(|e| -> errors::Error {
+        if e.kind() == io::ErrorKind::NotFound {
+            errors::ErrorKind::Abort("mandb not found, is man-db installed?".to_owned()).into()
+        } else {
+            e.into()
+        }
+    })?;
+
+    if !status.success() {
+        return Err(errors::ErrorKind::Abort(format!("mandb failed with {}", status)).into());
+    }
+
+    Ok(())
+}
+
+
+/// Register the man dir with man-db and remember it in the config.
+pub fn enable(env: &Environ) -> errors::Result<()> {
+    env.config.try_set_update_man_path(true)?;
+
+    fs::create_dir_all(&env.man_dir)?;
+    add_manpath(&env.home, &env.man_dir)?;
+    link_man3(&env.man_dir, env.config.source())?;
+    run_mandb(&env.man_dir, true)
+}
+
+/// Undo `enable`: the manpath entry, the `man3` link and the man-db
+/// databases in the man dir are removed. Cached pages are kept.
+pub fn disable(env: &Environ) -> errors::Result<()> {
+    env.config.try_set_update_man_path(false)?;
+
+    remove_manpath(&env.home, &env.man_dir)?;
+    unlink_man3(&env.man_dir)?;
+
+    for name in MANDB_FILES {
+        let path = env.man_dir.join(name);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Bring the `man3` link and the man-db index up to date after pages or the
+/// source changed, if the integration is enabled.
+pub fn update(env: &Environ, quiet: bool) -> errors::Result<()> {
+    if !env.config.try_update_man_path()? {
+        return Ok(());
+    }
+
+    link_man3(&env.man_dir, env.config.source())?;
+    run_mandb(&env.man_dir, quiet)
+}
+
+pub fn status(env: &Environ) -> errors::Result<Status> {
+    let man3 = env.man_dir.join("man3");
+    let is_link = fs::symlink_metadata(&man3).map(|m| m.file_type().is_symlink()).unwrap_or(false);
+
+    Ok(Status {
+        enabled: env.config.try_update_man_path()?,
+        registered: has_manpath(&env.home, &env.man_dir)?,
+        man3: if is_link { Some(fs::read_link(&man3)?) } else { None },
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::{Path, PathBuf};
+
+    use super::{add_manpath, has_manpath, link_man3, remove_manpath, unlink_man3};
+    use ::config::Source;
+
+    fn temp_home(name: &str) -> PathBuf {
+        let home = env::temp_dir().join("cppman-rs-test-mandb").join(name);
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(&home).unwrap();
+        home
+    }
+
+    fn read(path: &Path) -> String {
+        let mut s = String::new();
+        File::open(path).unwrap().read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_manpath_add_remove_is_idempotent() {
+        let home = temp_home("manpath");
+        let man_dir = home.join(".local/share/man");
+        let manpath = home.join(".manpath");
+        let user_lines = format!("# mine\nMANDATORY_MANPATH\t/opt/man\nMANPATH_MAP\t{0}/bin\t{0}\n",
+                                 man_dir.display());
+        File::create(&manpath).unwrap().write_all(user_lines.as_bytes()).unwrap();
+
+        assert!(!has_manpath(&home, &man_dir).unwrap());
+        assert!(add_manpath(&home, &man_dir).unwrap());
+        assert!(!add_manpath(&home, &man_dir).unwrap());
+        assert!(has_manpath(&home, &man_dir).unwrap());
+        assert_eq!(read(&manpath).matches("MANDATORY_MANPATH").count(), 2);
+
+        assert!(remove_manpath(&home, &man_dir).unwrap());
+        assert!(!remove_manpath(&home, &man_dir).unwrap());
+        assert_eq!(read(&manpath), user_lines);
+    }
+
+    #[test]
+    fn test_manpath_file_removed_when_empty() {
+        let home = temp_home("manpath-empty");
+        let man_dir = home.join("man");
+
+        assert!(!remove_manpath(&home, &man_dir).unwrap());
+        assert!(add_manpath(&home, &man_dir).unwrap());
+        assert!(remove_manpath(&home, &man_dir).unwrap());
+        assert!(!home.join(".manpath").exists());
+    }
+
+    #[test]
+    fn test_man3_switches_source() {
+        let man_dir = temp_home("man3-switch").join("man");
+
+        link_man3(&man_dir, Source::CPlusPlus).unwrap();
+        link_man3(&man_dir, Source::CPlusPlus).unwrap();
+        assert_eq!(fs::read_link(man_dir.join("man3")).unwrap(), Path::new("cplusplus.com"));
+
+        link_man3(&man_dir, Source::CppReference).unwrap();
+        assert_eq!(fs::read_link(man_dir.join("man3")).unwrap(), Path::new("cppreference.com"));
+
+        assert!(unlink_man3(&man_dir).unwrap());
+        assert!(!unlink_man3(&man_dir).unwrap());
+        assert!(man_dir.join("cppreference.com").is_dir());
+    }
+
+    #[test]
+    fn test_man3_existing_dir() {
+        let man_dir = temp_home("man3-dir").join("man");
+
+        // An empty directory is replaced
+        fs::create_dir_all(man_dir.join("man3")).unwrap();
+        link_man3(&man_dir, Source::CPlusPlus).unwrap();
+        assert!(fs::symlink_metadata(man_dir.join("man3")).unwrap().file_type().is_symlink());
+
+        // One with pages in it is kept
+        fs::remove_file(man_dir.join("man3")).unwrap();
+        fs::create_dir_all(man_dir.join("man3")).unwrap();
+        File::create(man_dir.join("man3/mine.3")).unwrap();
+        assert!(link_man3(&man_dir, Source::CPlusPlus).is_err());
+        assert!(!unlink_man3(&man_dir).unwrap());
+        assert!(man_dir.join("man3/mine.3").exists());
+    }
+}
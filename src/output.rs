@@ -0,0 +1,132 @@
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+
+use serde_json;
+
+use ::errors;
+
+
+#[derive(Copy, Clone, PartialEq)] pub enum OutputFormat { Text, Json, Ndjson }
+
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", Into::<&'static str>::into(*self))
+    }
+}
+
+impl OutputFormat {
+    pub fn try_from(s: &str) -> errors::Result<OutputFormat> {
+        match s {
+            "text"   => Ok(OutputFormat::Text),
+            "json"   => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _        => Err(errors::ErrorKind::ParseOutputFormat(s.to_owned()).into()),
+        }
+    }
+}
+
+impl Into<&'static str> for OutputFormat {
+    fn into(self) -> &'static str {
+        match self {
+            OutputFormat::Text   => "text",
+            OutputFormat::Json   => "json",
+            OutputFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+impl Default for OutputFormat {
+    fn default() -> OutputFormat {
+        OutputFormat::Text
+    }
+}
+
+
+/// An index entry as reported to other tools.
+#[derive(Serialize)]
+pub struct PageEntry {
+    pub name: String,
+    pub url: String,
+    pub source: String,
+    pub cached_path: String,
+    pub cached: bool,
+}
+
+
+/// A name that couldn't be resolved, reported among the entries.
+#[derive(Serialize)]
+pub struct EntryError {
+    pub query: String,
+    pub error: String,
+}
+
+
+/// Writes page entries in one of the machine-readable formats.
+///
+/// NDJSON entries are written and flushed one by one, JSON ones are collected
+/// into a single array written by `finish`. Errors are written as objects of
+/// their own in both, and to stderr as text.
+pub struct EntryWriter {
+    format: OutputFormat,
+    entries: Vec<serde_json::Value>,
+}
+
+impl EntryWriter {
+    pub fn new(format: OutputFormat) -> EntryWriter {
+        EntryWriter {
+            format: format,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, entry: PageEntry) -> errors::Result<()> {
+        match self.format {
+            OutputFormat::Text => {
+                println!("{}\t{}\t{}", entry.name, entry.url,
+                         if entry.cached { entry.cached_path.as_str() } else { "-" });
+            },
+            _                  => self.write_object(serde_json::to_value(&entry)?)?,
+        }
+
+        Ok(())
+    }
+
+    /// Report that `query` couldn't be resolved because of `error`.
+    pub fn write_error(&mut self, query: &str, error: &errors::Error) -> errors::Result<()> {
+        match self.format {
+            OutputFormat::Text => writeln!(&mut io::stderr(), "cppman-rs: {}", error)?,
+            _                  => self.write_object(serde_json::to_value(&EntryError {
+                query: query.to_owned(),
+                error: error.to_string(),
+            })?)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> errors::Result<()> {
+        if self.format == OutputFormat::Json {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            serde_json::to_writer_pretty(&mut stdout, &self.entries)?;
+            writeln!(stdout)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_object(&mut self, object: serde_json::Value) -> errors::Result<()> {
+        if self.format == OutputFormat::Json {
+            self.entries.push(object);
+            return Ok(());
+        }
+
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        serde_json::to_writer(&mut stdout, &object)?;
+        writeln!(stdout)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+//! Crawling rules published by sites: `robots.txt` and sitemaps.
+
+use std::io::Read;
+use std::time::Duration;
+
+use flate2::read::GzDecoder;
+use regex::{self, Regex};
+use url::Url;
+
+
+/// Name matched against `User-agent` lines.
+pub const ROBOT_NAME: &'static str = "cppman-rs";
+
+
+lazy_static! {
+    static ref LOC: Regex = Regex::new("(?s)<loc>\\s*(.*?)\\s*</loc>").unwrap();
+    static ref SITEMAP_INDEX: Regex = Regex::new("<sitemapindex[\\s>]").unwrap();
+}
+
+
+struct Rule {
+    allow: bool,
+    /// Length of the pattern, longer ones take precedence.
+    len: usize,
+    pattern: Regex,
+}
+
+/// Rules from a `robots.txt` that apply to us.
+pub struct Robots {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+    sitemaps: Vec<String>,
+}
+
+impl Robots {
+    /// Rules for a site without a usable `robots.txt`.
+    pub fn allow_all() -> Robots {
+        Robots {
+            rules: Vec::new(),
+            crawl_delay: None,
+            sitemaps: Vec::new(),
+        }
+    }
+
+    /// Rules for a site that refuses access to `robots.txt`.
+    pub fn disallow_all() -> Robots {
+        Robots {
+            rules: vec![Rule { allow: false, len: 1, pattern: Regex::new("^/").unwrap() }],
+            crawl_delay: None,
+            sitemaps: Vec::new(),
+        }
+    }
+
+    /// Parse `robots.txt`, keeping the group for `ROBOT_NAME`, or the one for
+    /// `*` if there is none.
+    pub fn parse(text: &str) -> Robots {
+        // (agents, rules, crawl delay) of each group
+        let mut groups: Vec<(Vec<String>, Vec<Rule>, Option<Duration>)> = Vec::new();
+        let mut sitemaps = Vec::new();
+        let mut in_agents = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let (field, value) = match line.find(':') {
+                Some(pos) => (line[..pos].trim().to_lowercase(), line[pos+1..].trim()),
+                None      => continue,
+            };
+
+            match field.as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push((Vec::new(), Vec::new(), None));
+                        in_agents = true;
+                    }
+                    groups.last_mut().unwrap().0.push(value.to_lowercase());
+                },
+                "allow" | "disallow" => {
+                    in_agents = false;
+                    // An empty `Disallow` allows everything
+                    if value.is_empty() {
+                        continue;
+                    }
+                    if let Some(group) = groups.last_mut() {
+                        group.1.push(Rule {
+                            allow: field == "allow",
+                            len: value.len(),
+                            pattern: pattern_regex(value),
+                        });
+                    }
+                },
+                "crawl-delay" => {
+                    in_agents = false;
+                    if let (Some(group), Ok(secs)) = (groups.last_mut(), value.parse::<f64>()) {
+                        if secs >= 0.0 {
+                            group.2 = Some(Duration::from_millis((secs * 1000.0) as u64));
+                        }
+                    }
+                },
+                "sitemap" => sitemaps.push(value.to_owned()),
+                _ => {},
+            }
+        }
+
+        // Agents are lower cased already, `cppman-rs/1.0` names us too
+        let ours = groups.iter().position(|g| g.0.iter().any(|a| a.split('/').next() == Some(ROBOT_NAME)))
+            .or(groups.iter().position(|g| g.0.iter().any(|a| a == "*")));
+
+        match ours {
+            Some(i) => {
+                let (_, rules, crawl_delay) = groups.swap_remove(i);
+                Robots { rules: rules, crawl_delay: crawl_delay, sitemaps: sitemaps }
+            },
+            None    => Robots { sitemaps: sitemaps, ..Robots::allow_all() },
+        }
+    }
+
+    /// Whether `url` may be fetched. The longest matching rule wins, `Allow`
+    /// on ties.
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None        => url.path().to_owned(),
+        };
+
+        self.rules.iter()
+            .filter(|r| r.pattern.is_match(&path))
+            .max_by_key(|r| (r.len, r.allow))
+            .map_or(true, |r| r.allow)
+    }
+
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+
+    /// Sitemaps listed in `robots.txt`.
+    pub fn sitemaps(&self) -> &[String] {
+        &self.sitemaps
+    }
+}
+
+
+/// Turn a rule path with `*` wildcards and an optional `$` end anchor into a
+/// regex matching from the start of the path.
+fn pattern_regex(pattern: &str) -> Regex {
+    let (pattern, anchored) = if pattern.ends_with('$') {
+        (&pattern[..pattern.len()-1], true)
+    } else {
+        (pattern, false)
+    };
+
+    let parts = pattern.split('*').map(regex::escape).collect::<Vec<_>>();
+    Regex::new(&format!("^{}{}", parts.join(".*"), if anchored { "$" } else { "" })).unwrap()
+}
+
+
+/// Contents of a sitemap or sitemap index.
+pub enum Sitemap {
+    Pages(Vec<String>),
+    /// Further sitemaps to read.
+    Index(Vec<String>),
+}
+
+/// Parse a sitemap, which may be gzip-compressed.
+pub fn parse_sitemap(body: &[u8]) -> Sitemap {
+    let mut text = String::new();
+    if body.starts_with(&[0x1f, 0x8b]) {
+        if let Ok(mut dec) = GzDecoder::new(body) {
+            let _ = dec.read_to_string(&mut text);
+        }
+    } else {
+        text = String::from_utf8_lossy(body).into_owned();
+    }
+
+    let locs = LOC.captures_iter(&text).map(|c| unescape_xml(&c[1])).collect();
+    if SITEMAP_INDEX.is_match(&text) {
+        Sitemap::Index(locs)
+    } else {
+        Sitemap::Pages(locs)
+    }
+}
+
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"")
+     .replace("&apos;", "'").replace("&amp;", "&")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use url::Url;
+
+    use super::{parse_sitemap, Robots, Sitemap};
+
+    fn allowed(robots: &Robots, path: &str) -> bool {
+        robots.is_allowed(&Url::parse("http://www.cplusplus.com").unwrap().join(path).unwrap())
+    }
+
+    #[test]
+    fn test_groups() {
+        let robots = Robots::parse("\
+            User-agent: *\n\
+            Disallow: /\n\
+            \n\
+            User-agent: googlebot\n\
+            User-agent: cppman-rs\n\
+            Disallow: /forum/\n\
+            Crawl-delay: 1.5\n\
+            Sitemap: http://www.cplusplus.com/sitemap.xml\n");
+
+        assert!(allowed(&robots, "/reference/vector/"));
+        assert!(!allowed(&robots, "/forum/general/"));
+        assert_eq!(robots.crawl_delay(), Some(Duration::from_millis(1500)));
+        assert_eq!(robots.sitemaps(), &["http://www.cplusplus.com/sitemap.xml".to_owned()]);
+
+        let robots = Robots::parse("User-agent: *\nDisallow: /private\n\nUser-agent: other\nDisallow: /\n");
+        assert!(!allowed(&robots, "/private/x"));
+        assert!(allowed(&robots, "/reference/"));
+
+        // Only our product token is ours, not any part of it
+        let robots = Robots::parse("User-agent: man\nUser-agent: Cppman\nDisallow: /\n\nUser-agent: *\nDisallow:\n");
+        assert!(allowed(&robots, "/reference/"));
+        let robots = Robots::parse("User-agent: *\nDisallow:\n\nUser-agent: CPPMAN-RS/1.0\nDisallow: /\n");
+        assert!(!allowed(&robots, "/reference/"));
+    }
+
+    #[test]
+    fn test_precedence_and_wildcards() {
+        let robots = Robots::parse("\
+            User-agent: *\n\
+            Disallow: /reference/\n\
+            Allow: /reference/vector/\n\
+            Disallow: /*.php$\n\
+            Disallow: /*?kw=\n\
+            Disallow:\n");
+
+        assert!(!allowed(&robots, "/reference/list/"));
+        assert!(allowed(&robots, "/reference/vector/vector/"));
+        assert!(!allowed(&robots, "/search.php"));
+        assert!(allowed(&robots, "/search.php5"));
+        assert!(!allowed(&robots, "/search/?kw=vector"));
+        assert!(allowed(&robots, "/doc/"));
+    }
+
+    #[test]
+    fn test_no_rules() {
+        assert!(allowed(&Robots::parse(""), "/reference/"));
+        assert!(!allowed(&Robots::disallow_all(), "/reference/"));
+    }
+
+    #[test]
+    fn test_sitemap() {
+        match parse_sitemap(b"<?xml version=\"1.0\"?><urlset>\
+                              <url><loc>http://www.cplusplus.com/reference/?a=1&amp;b=2</loc></url>\
+                              <url><loc> http://www.cplusplus.com/reference/vector/ </loc></url></urlset>") {
+            Sitemap::Pages(pages) => assert_eq!(pages, vec!["http://www.cplusplus.com/reference/?a=1&b=2",
+                                                            "http://www.cplusplus.com/reference/vector/"]),
+            Sitemap::Index(_)     => panic!("urlset parsed as an index"),
+        }
+
+        match parse_sitemap(b"<sitemapindex><sitemap><loc>http://x/s1.xml</loc></sitemap></sitemapindex>") {
+            Sitemap::Index(maps) => assert_eq!(maps, vec!["http://x/s1.xml"]),
+            Sitemap::Pages(_)    => panic!("index parsed as pages"),
+        }
+    }
+}
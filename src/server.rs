@@ -0,0 +1,451 @@
+//! Long-running mode answering documentation requests over stdio.
+//!
+//! Messages are JSON-RPC 2.0 objects, either framed LSP-style with a
+//! `Content-Length` header or written one per line. Replies use the same
+//! framing as the request they answer.
+//!
+//! Supported methods:
+//!
+//! * `initialize` — returns `{"capabilities": {"hoverProvider": true}}`;
+//! * `hover` (also `cppman/hover`) — params `{"symbol": "std::vector::emplace_back",
+//!   "format": "markdown" | "plaintext", "width": 80}`, returns
+//!   `{"name", "url", "contents": {"kind", "value"}}`;
+//! * `shutdown` and `exit`.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+
+use regex::Regex;
+use serde_json::{self, Value};
+
+use ::cppman::Cppman;
+use ::errors;
+
+
+/// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// Application-defined: no index entry matches the symbol.
+const NOT_FOUND: i64 = -32001;
+
+/// Indentation of section bodies in `groff -Tascii -man` output.
+const BODY_INDENT: usize = 7;
+
+const DEFAULT_WIDTH: usize = 80;
+
+
+lazy_static! {
+    static ref OVERSTRIKE: Regex = Regex::new(".\x08").unwrap();
+    static ref SECTION: Regex = Regex::new("^[A-Z][A-Z0-9 ()-]*$").unwrap();
+}
+
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum Framing { Header, Line }
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum HoverFormat { Markdown, PlainText }
+
+
+pub struct Server<'a> {
+    cppman: &'a Cppman,
+    /// Rendered pages keyed by page name and width.
+    rendered: HashMap<(String, usize), String>,
+    shutdown: bool,
+}
+
+impl<'a> Server<'a> {
+    pub fn new(cppman: &'a Cppman) -> Server<'a> {
+        Server {
+            cppman: cppman,
+            rendered: HashMap::new(),
+            shutdown: false,
+        }
+    }
+
+    /// Serve requests until `exit` is received or the input is closed.
+    ///
+    /// Malformed messages are answered with an error and the next one is
+    /// read, only I/O errors end the loop.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: &mut R, output: &mut W) -> errors::Result<()> {
+        while let Some((framing, body)) = read_message(input)? {
+            let reply = match body.and_then(|body| {
+                serde_json::from_str::<Value>(&body).map_err(|e| (PARSE_ERROR, e.to_string()))
+            }) {
+                Ok(request) => {
+                    if request.get("method").and_then(Value::as_str) == Some("exit") {
+                        return Ok(());
+                    }
+                    self.handle(&request)
+                },
+                Err((code, msg)) => Some(error_reply(Value::Null, code, &msg)),
+            };
+
+            if let Some(reply) = reply {
+                write_message(output, framing, &reply)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle a single request, returning the reply unless it's a
+    /// notification.
+    fn handle(&mut self, request: &Value) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let method = match request.get("method").and_then(Value::as_str) {
+            Some(method) => method,
+            None         => return Some(error_reply(id.unwrap_or(Value::Null),
+                                                    INVALID_REQUEST, "missing method")),
+        };
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+        let result = match method {
+            "initialize"               => Ok(json!({ "capabilities": { "hoverProvider": true } })),
+            "shutdown"                 => { self.shutdown = true; Ok(Value::Null) },
+            "hover" | "cppman/hover"   => self.hover(&params),
+            _                          => Err((METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+        };
+
+        // Notifications don't get replies
+        let id = match id {
+            Some(id) => id,
+            None     => return None,
+        };
+
+        Some(match result {
+            Ok(result)          => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, msg))    => error_reply(id, code, &msg),
+        })
+    }
+
+    fn hover(&mut self, params: &Value) -> Result<Value, (i64, String)> {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "server is shutting down".to_owned()));
+        }
+
+        let symbol = params.get("symbol").and_then(Value::as_str)
+            .ok_or((INVALID_PARAMS, "missing 'symbol'".to_owned()))?;
+        let format = match params.get("format").and_then(Value::as_str) {
+            None | Some("markdown") => HoverFormat::Markdown,
+            Some("plaintext")       => HoverFormat::PlainText,
+            Some(f)                 => return Err((INVALID_PARAMS, format!("unknown format '{}'", f))),
+        };
+        let width = params.get("width").and_then(Value::as_u64).map_or(DEFAULT_WIDTH, |w| w as usize);
+
+        let (name, url) = self.cppman.lookup(symbol).map_err(|e| match *e.kind() {
+            errors::ErrorKind::NoManualEntry(_) => (NOT_FOUND, e.to_string()),
+            _                                   => (INTERNAL_ERROR, e.to_string()),
+        })?;
+
+        let key = (name.clone(), width);
+        if !self.rendered.contains_key(&key) {
+            let text = self.cppman.render(&name, &url, width)
+                .map_err(|e| (INTERNAL_ERROR, e.to_string()))?;
+            self.rendered.insert(key.clone(), OVERSTRIKE.replace_all(&text, "").into_owned());
+        }
+
+        let text = &self.rendered[&key];
+        let (kind, value) = match format {
+            HoverFormat::Markdown  => ("markdown", to_markdown(text)),
+            HoverFormat::PlainText => ("plaintext", text.clone()),
+        };
+
+        Ok(json!({
+            "name": name,
+            "url": url,
+            "contents": { "kind": kind, "value": value },
+        }))
+    }
+}
+
+
+fn error_reply(id: Value, code: i64, msg: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": msg } })
+}
+
+/// Read one message, skipping blank lines between them. A message that is
+/// framed but can't be read has the JSON-RPC error to reply with instead of a
+/// body.
+fn read_message<R: BufRead>(input: &mut R)
+        -> errors::Result<Option<(Framing, Result<String, (i64, String)>)>> {
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if trimmed.starts_with('{') {
+            return Ok(Some((Framing::Line, Ok(trimmed.to_owned()))));
+        }
+
+        break;
+    }
+
+    // LSP-style headers, terminated by an empty line
+    let mut length = None;
+    loop {
+        let trimmed = line.trim().to_owned();
+        if trimmed.is_empty() {
+            break;
+        }
+
+        if let Some(pos) = trimmed.find(':') {
+            if trimmed[..pos].eq_ignore_ascii_case("content-length") {
+                length = trimmed[pos+1..].trim().parse::<usize>().ok();
+            }
+        }
+
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+    }
+
+    let length = match length {
+        Some(length) => length,
+        None         => return Ok(Some((Framing::Header,
+                                        Err((INVALID_REQUEST, "missing Content-Length header".to_owned()))))),
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    Ok(Some((Framing::Header, String::from_utf8(body).map_err(|e| (PARSE_ERROR, e.to_string())))))
+}
+
+fn write_message<W: Write>(output: &mut W, framing: Framing, message: &Value) -> errors::Result<()> {
+    let body = serde_json::to_string(message)?;
+
+    match framing {
+        Framing::Header => write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?,
+        Framing::Line   => writeln!(output, "{}", body)?,
+    }
+
+    output.flush().map_err(Into::into)
+}
+
+/// Turn a page rendered by `groff -Tascii` into Markdown.
+///
+/// Section headings become Markdown headings, SYNOPSIS and EXAMPLE become C++
+/// code blocks and the running header and footer are dropped.
+fn to_markdown(text: &str) -> String {
+    let lines = text.lines().collect::<Vec<_>>();
+    let body = if lines.len() > 2 { &lines[1..lines.len()-1] } else { &lines[..] };
+
+    let mut markdown = String::new();
+    let mut in_code = false;
+
+    for line in body {
+        if SECTION.is_match(line) {
+            if in_code {
+                markdown.push_str("```\n");
+            }
+
+            markdown.push_str(&format!("\n### {}\n\n", line.trim()));
+            in_code = *line == "SYNOPSIS" || *line == "EXAMPLE";
+            if in_code {
+                markdown.push_str("```cpp\n");
+            }
+        } else if in_code {
+            let indent = line.len() - line.trim_left_matches(' ').len();
+            markdown.push_str(line[indent.min(BODY_INDENT)..].trim_right());
+            markdown.push('\n');
+        } else {
+            // Paragraphs are separated by blank lines already
+            markdown.push_str(line.trim());
+            markdown.push('\n');
+        }
+    }
+
+    if in_code {
+        markdown.push_str("```\n");
+    }
+
+    markdown.trim().to_owned()
+}
+
+
+/// Serve requests on stdin and stdout.
+pub fn serve(cppman: &Cppman) -> errors::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    Server::new(cppman).run(&mut stdin.lock(), &mut stdout.lock())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+
+    use rusqlite::Connection;
+    use serde_json::Value;
+
+    use super::{read_message, to_markdown, write_message, Framing, Server, INVALID_PARAMS, INVALID_REQUEST,
+                METHOD_NOT_FOUND, NOT_FOUND, PARSE_ERROR};
+    use ::config::Source;
+    use ::cppman::Cppman;
+    use ::environ::{Environ, Paths};
+    use ::index::{self, PageInfo};
+
+    const PAGE: &'static str = "\
+vector::begin(3)          C++ Programmer's Manual          vector::begin(3)
+
+NAME
+       vector::begin - Return iterator to beginning
+
+SYNOPSIS
+       #include <vector>
+         iterator begin() noexcept;
+
+DESCRIPTION
+       Returns an iterator pointing to the first element.
+
+cplusplus.com                  2017-06-01                  vector::begin(3)
+";
+
+    /// Environment whose index has `vector::begin` only.
+    fn environ(name: &str) -> Environ {
+        let root = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        let paths = Paths::under(&root);
+        fs::create_dir_all(&paths.data_dir).unwrap();
+
+        let conn = Connection::open(paths.data_dir.join("index.db")).unwrap();
+        index::upgrade(&conn).unwrap();
+        index::insert(&conn, Source::CPlusPlus, "vector::begin",
+                      "http://www.cplusplus.com/reference/vector/vector/begin/", &PageInfo::default()).unwrap();
+
+        Environ::from_paths(paths).unwrap()
+    }
+
+    fn error_code(reply: &Value) -> Option<i64> {
+        reply.pointer("/error/code").and_then(Value::as_i64)
+    }
+
+    #[test]
+    fn test_read_message() {
+        let mut bytes = b"\n{\"id\": 1}\nContent-Length: 9\r\n\r\n{\"id\": 2}\
+                          X-Other: 1\r\n\r\n\
+                          Content-Length: 2\r\n\r\n".to_vec();
+        // Not UTF-8
+        bytes.extend_from_slice(&[0xff, 0xfe]);
+        let mut input = Cursor::new(bytes);
+
+        let (framing, body) = read_message(&mut input).unwrap().unwrap();
+        assert!(framing == Framing::Line);
+        assert_eq!(body, Ok("{\"id\": 1}".to_owned()));
+
+        let (framing, body) = read_message(&mut input).unwrap().unwrap();
+        assert!(framing == Framing::Header);
+        assert_eq!(body, Ok("{\"id\": 2}".to_owned()));
+
+        let (_, body) = read_message(&mut input).unwrap().unwrap();
+        assert_eq!(body.unwrap_err().0, INVALID_REQUEST);
+
+        let (_, body) = read_message(&mut input).unwrap().unwrap();
+        assert_eq!(body.unwrap_err().0, PARSE_ERROR);
+
+        assert!(read_message(&mut input).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_write_message() {
+        let message = json!({ "id": 1 });
+
+        let mut output = Vec::new();
+        write_message(&mut output, Framing::Header, &message).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Content-Length: 8\r\n\r\n{\"id\":1}");
+
+        let mut output = Vec::new();
+        write_message(&mut output, Framing::Line, &message).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "{\"id\":1}\n");
+    }
+
+    #[test]
+    fn test_run_survives_bad_messages() {
+        let env = environ("cppman-rs-test-server-run");
+        let cm = Cppman::new_default(&env);
+
+        let mut input = Cursor::new("X-Other: 1\r\n\r\n{not json}\n\
+                                     {\"jsonrpc\": \"2.0\", \"id\": 1, \"method\": \"initialize\"}\n\
+                                     {\"method\": \"exit\"}\n\
+                                     {\"id\": 2, \"method\": \"initialize\"}\n".as_bytes());
+        let mut output = Vec::new();
+        Server::new(&cm).run(&mut input, &mut output).unwrap();
+
+        let mut output = Cursor::new(output);
+        let mut replies = Vec::new();
+        while let Some((framing, body)) = read_message(&mut output).unwrap() {
+            replies.push((framing, ::serde_json::from_str::<Value>(&body.unwrap()).unwrap()));
+        }
+
+        assert_eq!(replies.len(), 3);
+        assert!(replies[0].0 == Framing::Header);
+        assert_eq!(error_code(&replies[0].1), Some(INVALID_REQUEST));
+        assert!(replies[1].0 == Framing::Line);
+        assert_eq!(error_code(&replies[1].1), Some(PARSE_ERROR));
+        assert_eq!(replies[2].1["id"], json!(1));
+        assert_eq!(replies[2].1["result"]["capabilities"]["hoverProvider"], Value::Bool(true));
+    }
+
+    #[test]
+    fn test_handle() {
+        let env = environ("cppman-rs-test-server-handle");
+        let cm = Cppman::new_default(&env);
+        let mut server = Server::new(&cm);
+
+        assert_eq!(server.handle(&json!({ "method": "initialize" })), None);
+        assert_eq!(error_code(&server.handle(&json!({ "id": 1, "method": "definition" })).unwrap()),
+                   Some(METHOD_NOT_FOUND));
+        assert_eq!(error_code(&server.handle(&json!({ "id": 1 })).unwrap()), Some(INVALID_REQUEST));
+
+        let hover = |server: &mut Server, params: Value| {
+            server.handle(&json!({ "id": 7, "method": "hover", "params": params })).unwrap()
+        };
+        assert_eq!(error_code(&hover(&mut server, json!({}))), Some(INVALID_PARAMS));
+        assert_eq!(error_code(&hover(&mut server, json!({ "symbol": "begin", "format": "html" }))),
+                   Some(INVALID_PARAMS));
+        assert_eq!(error_code(&hover(&mut server, json!({ "symbol": "no_such_thing" }))), Some(NOT_FOUND));
+
+        // Rendered already, so that groff isn't needed
+        server.rendered.insert(("vector::begin".to_owned(), 40), PAGE.to_owned());
+        let reply = hover(&mut server, json!({ "symbol": "vector::begin", "width": 40, "format": "plaintext" }));
+        assert_eq!(reply["id"], json!(7));
+        assert_eq!(reply["result"]["name"], json!("vector::begin"));
+        assert_eq!(reply["result"]["url"], json!("http://www.cplusplus.com/reference/vector/vector/begin/"));
+        assert_eq!(reply["result"]["contents"], json!({ "kind": "plaintext", "value": PAGE }));
+
+        let reply = hover(&mut server, json!({ "symbol": "vector::begin", "width": 40 }));
+        assert_eq!(reply["result"]["contents"]["kind"], json!("markdown"));
+
+        assert_eq!(server.handle(&json!({ "id": 8, "method": "shutdown" })).unwrap()["result"], Value::Null);
+        assert_eq!(error_code(&hover(&mut server, json!({ "symbol": "vector::begin" }))), Some(INVALID_REQUEST));
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        assert_eq!(to_markdown(PAGE),
+                   "### NAME\n\n\
+                    vector::begin - Return iterator to beginning\n\n\n\
+                    ### SYNOPSIS\n\n\
+                    ```cpp\n\
+                    #include <vector>\n\
+                    \x20 iterator begin() noexcept;\n\
+                    \n\
+                    ```\n\n\
+                    ### DESCRIPTION\n\n\
+                    Returns an iterator pointing to the first element.");
+    }
+}
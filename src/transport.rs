@@ -0,0 +1,413 @@
+//! Where fetched pages come from.
+//!
+//! Besides the network, responses can be recorded into a directory and
+//! replayed from it later, so that indexing and caching run deterministically
+//! without network access. Each response is stored as two files named after
+//! the percent-encoded URL: the body, and `<name>.meta` holding the status,
+//! content type, redirects and final URL as JSON. Names too long for the file
+//! system keep the start of the encoded URL, followed by a hash of the URL.
+
+use std::cmp;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use encoding_rs::{Encoding, UTF_8};
+use regex::Regex;
+use reqwest::header::{ContentType, Location};
+use serde_json;
+use url::Url;
+
+use ::errors;
+use ::http::{Http, HttpConfig};
+
+
+/// Redirects followed for a single request at most.
+const MAX_REDIRECTS: usize = 10;
+
+/// Longest file name of a response, leaving room for `.meta` within the 255
+/// bytes file systems commonly allow.
+const MAX_KEY_LEN: usize = 250;
+
+/// Bytes searched for a `<meta>` charset declaration.
+const SNIFF_LEN: usize = 1024;
+
+
+lazy_static! {
+    static ref CHARSET: Regex = Regex::new("(?i)charset\\s*=\\s*[\"']?([-_.:a-z0-9]+)").unwrap();
+    static ref META_CHARSET: Regex =
+        Regex::new("(?i)<meta\\s[^>]*charset\\s*=\\s*[\"']?([-_.:a-z0-9]+)").unwrap();
+    static ref HTML_START: Regex = Regex::new("(?i)^\\s*(<!doctype\\s+html|<html|<head|<body)").unwrap();
+}
+
+
+/// How pages are fetched.
+#[derive(Clone)]
+pub enum FetchMode {
+    Network,
+    /// Fetch from the network and store every response in the directory.
+    Record(PathBuf),
+    /// Answer from responses stored in the directory only.
+    Replay(PathBuf),
+}
+
+impl Default for FetchMode {
+    fn default() -> FetchMode {
+        FetchMode::Network
+    }
+}
+
+
+/// A response read in full.
+#[derive(Clone)]
+pub struct Fetched {
+    /// URL the body was served from, after redirects.
+    pub url: Url,
+    /// URLs that redirected to `url` in order, starting with the requested
+    /// one, empty if there were no redirects.
+    pub redirects: Vec<Url>,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Fetched {
+    pub fn is_success(&self) -> bool {
+        self.status >= 200 && self.status < 300
+    }
+
+    /// Whether the body is HTML, according to the content type or, lacking
+    /// one, to how it starts.
+    pub fn is_html(&self) -> bool {
+        match self.content_type {
+            Some(ref ct) => {
+                let mime = mime_type(ct);
+                mime == "text/html" || mime == "application/xhtml+xml"
+            },
+            None         => HTML_START.is_match(&String::from_utf8_lossy(self.sniffed())),
+        }
+    }
+
+    /// The body decoded from the charset in the content type or a `<meta>`
+    /// tag, UTF-8 if neither declares a known one. A byte order mark takes
+    /// precedence over both.
+    pub fn text(&self) -> String {
+        let (text, _, _) = self.encoding().decode(&self.body);
+        text.into_owned()
+    }
+
+    fn encoding(&self) -> &'static Encoding {
+        let declared = self.content_type.as_ref()
+            .and_then(|ct| CHARSET.captures(ct).map(|c| c[1].to_owned()))
+            .or_else(|| {
+                META_CHARSET.captures(&String::from_utf8_lossy(self.sniffed())).map(|c| c[1].to_owned())
+            });
+
+        declared.and_then(|label| Encoding::for_label(label.as_bytes())).unwrap_or(UTF_8)
+    }
+
+    /// Start of the body, where declarations are looked for.
+    fn sniffed(&self) -> &[u8] {
+        &self.body[..cmp::min(self.body.len(), SNIFF_LEN)]
+    }
+}
+
+/// Everything but the body of a recorded response.
+#[derive(Serialize, Deserialize)]
+struct Meta {
+    url: String,
+    #[serde(default)]
+    redirects: Vec<String>,
+    status: u16,
+    content_type: Option<String>,
+}
+
+
+pub trait Transport {
+    fn fetch(&self, url: &Url) -> errors::Result<Fetched>;
+
+    /// Fetch `url` and return the body, failing unless the request succeeded.
+    fn fetch_text(&self, url: &Url) -> errors::Result<String> {
+        let fetched = self.fetch(url)?;
+        if !fetched.is_success() {
+            return Err(errors::ErrorKind::HttpStatus(url.to_string(), fetched.status.to_string()).into());
+        }
+        Ok(fetched.text())
+    }
+}
+
+impl Transport for Http {
+    /// Redirects are followed here rather than by the client, so that the
+    /// chain can be recorded.
+    fn fetch(&self, url: &Url) -> errors::Result<Fetched> {
+        let mut url = url.clone();
+        let mut redirects = Vec::new();
+
+        loop {
+            let mut res = self.get(&url)?;
+
+            let location = res.headers().get::<Location>().map(|l| l.to_string());
+            if let (true, Some(location)) = (res.status().is_redirection(), location) {
+                let next = url.join(&location)?;
+                if redirects.len() >= MAX_REDIRECTS || next == url || redirects.contains(&next) {
+                    return Err(errors::ErrorKind::TooManyRedirects(redirects.first().unwrap_or(&url).to_string()).into());
+                }
+                redirects.push(url);
+                url = next;
+                continue;
+            }
+
+            let mut body = Vec::new();
+            res.read_to_end(&mut body)?;
+
+            return Ok(Fetched {
+                url: url,
+                redirects: redirects,
+                status: u16::from(res.status()),
+                content_type: res.headers().get::<ContentType>().map(|ct| ct.to_string()),
+                body: body,
+            });
+        }
+    }
+}
+
+
+pub struct RecordTransport {
+    inner: Rc<Transport>,
+    dir: PathBuf,
+}
+
+impl RecordTransport {
+    pub fn new<P: AsRef<Path>>(inner: Rc<Transport>, dir: P) -> errors::Result<RecordTransport> {
+        fs::create_dir_all(dir.as_ref())?;
+
+        Ok(RecordTransport {
+            inner: inner,
+            dir: dir.as_ref().to_owned(),
+        })
+    }
+}
+
+impl Transport for RecordTransport {
+    fn fetch(&self, url: &Url) -> errors::Result<Fetched> {
+        let fetched = self.inner.fetch(url)?;
+        save(&self.dir, url, &fetched)?;
+        Ok(fetched)
+    }
+}
+
+
+pub struct ReplayTransport {
+    dir: PathBuf,
+}
+
+impl ReplayTransport {
+    pub fn new<P: AsRef<Path>>(dir: P) -> ReplayTransport {
+        ReplayTransport { dir: dir.as_ref().to_owned() }
+    }
+}
+
+impl Transport for ReplayTransport {
+    /// URLs that weren't recorded are answered with 404.
+    fn fetch(&self, url: &Url) -> errors::Result<Fetched> {
+        let path = self.dir.join(key(url));
+
+        let meta = match File::open(path.with_extension("meta")) {
+            Ok(f)  => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(Fetched {
+                    url: url.clone(),
+                    redirects: Vec::new(),
+                    status: 404,
+                    content_type: None,
+                    body: Vec::new(),
+                });
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        let meta: Meta = serde_json::from_reader(meta)?;
+        let mut body = Vec::new();
+        File::open(&path)?.read_to_end(&mut body)?;
+
+        Ok(Fetched {
+            url: Url::parse(&meta.url)?,
+            redirects: meta.redirects.iter().map(|u| Url::parse(u)).collect::<Result<_, _>>()?,
+            status: meta.status,
+            content_type: meta.content_type,
+            body: body,
+        })
+    }
+}
+
+
+/// Store `fetched` as the response to `url` in `dir`.
+pub fn save(dir: &Path, url: &Url, fetched: &Fetched) -> errors::Result<()> {
+    let path = dir.join(key(url));
+
+    let meta = Meta {
+        url: fetched.url.to_string(),
+        redirects: fetched.redirects.iter().map(Url::to_string).collect(),
+        status: fetched.status,
+        content_type: fetched.content_type.clone(),
+    };
+
+    File::create(&path)?.write_all(&fetched.body)?;
+    serde_json::to_writer_pretty(File::create(path.with_extension("meta"))?, &meta)?;
+    Ok(())
+}
+
+/// Lower case media type of a content type, without parameters.
+fn mime_type(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or("").trim().to_lowercase()
+}
+
+/// File name for `url`: everything but ASCII letters, digits, `-` and `_`
+/// percent-encoded, so that names are flat and `.meta` can be appended.
+/// Longer than `MAX_KEY_LEN`, it is cut and `~` and the hash of the URL are
+/// appended, `~` being encoded otherwise.
+fn key(url: &Url) -> String {
+    let key = url.as_str().bytes().map(|b| {
+        if (b as char).is_ascii_alphanumeric() || b == b'-' || b == b'_' {
+            (b as char).to_string()
+        } else {
+            format!("%{:02X}", b)
+        }
+    }).collect::<String>();
+    if key.len() <= MAX_KEY_LEN {
+        return key;
+    }
+
+    // Keep escapes whole
+    let mut end = MAX_KEY_LEN - 17;
+    if let Some(pos) = key[end-2..end].find('%') {
+        end -= 2 - pos;
+    }
+    format!("{}~{:016x}", &key[..end], fnv1a(url.as_str().as_bytes()))
+}
+
+/// FNV-1a hash, enough to tell URLs with the same start apart.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Create the transport for `mode`.
+pub fn from_mode(mode: &FetchMode, config: &HttpConfig) -> errors::Result<Rc<Transport>> {
+    let transport: Rc<Transport> = match *mode {
+        FetchMode::Network         => Rc::new(Http::new(config)?),
+        FetchMode::Record(ref dir) => {
+            Rc::new(RecordTransport::new(Rc::new(Http::new(config)?), dir)?)
+        },
+        FetchMode::Replay(ref dir) => Rc::new(ReplayTransport::new(dir)),
+    };
+
+    Ok(transport)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use url::Url;
+
+    use super::{key, save, Fetched, ReplayTransport, Transport, MAX_KEY_LEN};
+
+    #[test]
+    fn test_key_is_flat() {
+        let url = Url::parse("http://www.cplusplus.com/reference/vector/vector/?kw=vector").unwrap();
+        assert_eq!(key(&url), "http%3A%2F%2Fwww%2Ecplusplus%2Ecom%2Freference%2Fvector%2Fvector%2F%3Fkw%3Dvector");
+    }
+
+    #[test]
+    fn test_long_key() {
+        let dir = env::temp_dir().join("cppman-rs-test-transport-long-key");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let long = format!("http://en.cppreference.com/w/cpp/{}", "a/".repeat(133));
+        let urls = [Url::parse(&format!("{}x", long)).unwrap(), Url::parse(&format!("{}y", long)).unwrap()];
+        assert_eq!(urls[0].as_str().len(), 300);
+
+        let keys = urls.iter().map(key).collect::<Vec<_>>();
+        assert!(keys[0] != keys[1]);
+        for (url, key) in urls.iter().zip(&keys) {
+            assert!(key.len() <= MAX_KEY_LEN);
+            assert!(key.starts_with("http%3A%2F%2Fen%2Ecppreference%2Ecom%2Fw%2Fcpp%2Fa%2F"));
+            assert!(!key.contains('.'));
+
+            save(&dir, url, &Fetched {
+                url: url.clone(),
+                redirects: Vec::new(),
+                status: 200,
+                content_type: None,
+                body: url.as_str().as_bytes().to_vec(),
+            }).unwrap();
+        }
+
+        let replay = ReplayTransport::new(&dir);
+        for url in &urls {
+            assert_eq!(replay.fetch_text(url).unwrap(), url.as_str());
+        }
+    }
+
+    #[test]
+    fn test_replay_round_trip() {
+        let dir = env::temp_dir().join("cppman-rs-test-transport");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let url = Url::parse("http://www.cplusplus.com/reference/").unwrap();
+        let fetched = Fetched {
+            url: Url::parse("http://www.cplusplus.com/reference/index/").unwrap(),
+            redirects: vec![url.clone()],
+            status: 200,
+            content_type: Some("text/html; charset=utf-8".to_owned()),
+            body: b"<h1>Reference</h1>".to_vec(),
+        };
+        save(&dir, &url, &fetched).unwrap();
+
+        let replay = ReplayTransport::new(&dir);
+        let replayed = replay.fetch(&url).unwrap();
+        assert_eq!(replayed.url, fetched.url);
+        assert_eq!(replayed.redirects, fetched.redirects);
+        assert_eq!(replayed.content_type, fetched.content_type);
+        assert_eq!(replay.fetch_text(&url).unwrap(), "<h1>Reference</h1>");
+
+        let missing = Url::parse("http://www.cplusplus.com/missing/").unwrap();
+        assert_eq!(replay.fetch(&missing).unwrap().status, 404);
+        assert!(replay.fetch_text(&missing).is_err());
+    }
+
+    fn fetched(content_type: Option<&str>, body: &[u8]) -> Fetched {
+        Fetched {
+            url: Url::parse("http://www.cplusplus.com/reference/").unwrap(),
+            redirects: Vec::new(),
+            status: 200,
+            content_type: content_type.map(str::to_owned),
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_charset() {
+        assert_eq!(fetched(Some("text/html; charset=ISO-8859-1"), b"caf\xe9").text(), "caf\u{e9}");
+        assert_eq!(fetched(Some("text/html"), b"<meta charset=\"windows-1252\">\x93").text(),
+                   "<meta charset=\"windows-1252\">\u{201c}");
+        assert_eq!(fetched(None, b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=latin1\">\xe9")
+                       .text().chars().last(), Some('\u{e9}'));
+        assert_eq!(fetched(Some("text/html; charset=utf-8"), "caf\u{e9}".as_bytes()).text(), "caf\u{e9}");
+        assert_eq!(fetched(None, b"caf\xe9").text(), "caf\u{fffd}");
+    }
+
+    #[test]
+    fn test_is_html() {
+        assert!(fetched(Some("text/html; charset=utf-8"), b"").is_html());
+        assert!(fetched(Some("Application/XHTML+xml"), b"").is_html());
+        assert!(!fetched(Some("image/png"), b"<html>").is_html());
+        assert!(fetched(None, b"\n<!DOCTYPE html><html>").is_html());
+        assert!(!fetched(None, b"%PDF-1.4").is_html());
+    }
+}
@@ -0,0 +1,421 @@
+//! Interactive terminal browser for the index.
+//!
+//! The screen is split into a search box on top, the matching names on the
+//! left and the rendered page on the right. Typing edits the search, `Enter`
+//! moves to the page, where `n`/`N` select see-also links, `Enter` follows
+//! them and `Backspace` goes back through the history.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use regex::Regex;
+use termion::{self, clear, cursor, style};
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
+
+use ::cppman::Cppman;
+use ::errors;
+
+
+/// Maximum number of search results listed.
+const MAX_RESULTS: usize = 500;
+
+
+lazy_static! {
+    static ref OVERSTRIKE: Regex = Regex::new(".\x08").unwrap();
+    static ref LINK: Regex = Regex::new("([A-Za-z_~][A-Za-z0-9_:<>=!~*\\[\\]+-]*)\\(3[a-z]*\\)").unwrap();
+}
+
+
+#[derive(Copy, Clone, PartialEq)]
+enum Focus { Search, Page }
+
+/// A see-also link found in the rendered page.
+struct Link {
+    line: usize,
+    start: usize,
+    end: usize,
+    name: String,
+}
+
+struct Browser<'a> {
+    cppman: &'a Cppman,
+
+    query: String,
+    results: Vec<(String, String)>,
+    selected: usize,
+    list_offset: usize,
+
+    focus: Focus,
+    /// Name of the page shown, its lines and links.
+    page: Option<String>,
+    lines: Vec<String>,
+    links: Vec<Link>,
+    link: Option<usize>,
+    page_offset: usize,
+
+    /// Pages visited before the current one.
+    history: Vec<String>,
+    rendered: HashMap<(String, usize), Vec<String>>,
+    status: String,
+}
+
+impl<'a> Browser<'a> {
+    fn new(cppman: &'a Cppman) -> Browser<'a> {
+        Browser {
+            cppman: cppman,
+            query: String::new(),
+            results: Vec::new(),
+            selected: 0,
+            list_offset: 0,
+            focus: Focus::Search,
+            page: None,
+            lines: Vec::new(),
+            links: Vec::new(),
+            link: None,
+            page_offset: 0,
+            history: Vec::new(),
+            rendered: HashMap::new(),
+            status: String::new(),
+        }
+    }
+
+    fn update_results(&mut self) {
+        self.selected = 0;
+        self.list_offset = 0;
+        self.status.clear();
+
+        if self.query.is_empty() {
+            self.results.clear();
+            return;
+        }
+
+        match self.cppman.search(&self.query) {
+            Ok(mut results) => {
+                results.truncate(MAX_RESULTS);
+                self.results = results;
+            },
+            Err(e) => {
+                self.results.clear();
+                self.status = e.to_string();
+            },
+        }
+    }
+
+    /// Show the page `name` resolves to, remembering the current one.
+    fn open(&mut self, name: &str, url: Option<&str>, width: usize) {
+        let (name, url) = match url {
+            Some(url) => (name.to_owned(), url.to_owned()),
+            None      => match self.cppman.lookup(name) {
+                Ok(pair) => pair,
+                Err(e)   => { self.status = e.to_string(); return; },
+            },
+        };
+
+        if let Some(current) = self.page.take() {
+            if current != name {
+                self.history.push(current);
+            }
+        }
+
+        self.show(&name, &url, width);
+    }
+
+    fn show(&mut self, name: &str, url: &str, width: usize) {
+        let key = (name.to_owned(), width);
+        if !self.rendered.contains_key(&key) {
+            match self.cppman.render(name, url, width) {
+                Ok(text) => {
+                    let text = OVERSTRIKE.replace_all(&text, "");
+                    self.rendered.insert(key.clone(), text.lines().map(str::to_owned).collect());
+                },
+                Err(e) => { self.status = e.to_string(); return; },
+            }
+        }
+
+        self.lines = self.rendered[&key].clone();
+        self.links = self.lines.iter().enumerate().flat_map(|(i, line)| {
+            LINK.captures_iter(line).map(|c| {
+                let m = c.get(0).unwrap();
+                Link { line: i, start: m.start(), end: m.end(), name: c[1].to_owned() }
+            }).collect::<Vec<_>>()
+        }).collect();
+        self.link = None;
+        self.page_offset = 0;
+        self.page = Some(name.to_owned());
+        self.focus = Focus::Page;
+        self.status = url.to_owned();
+    }
+
+    fn back(&mut self, width: usize) {
+        if let Some(name) = self.history.pop() {
+            match self.cppman.lookup(&name) {
+                Ok((name, url)) => self.show(&name, &url, width),
+                Err(e)          => self.status = e.to_string(),
+            }
+        }
+    }
+
+    /// Select the next (or previous) link and scroll it into view.
+    fn cycle_link(&mut self, forward: bool, height: usize) {
+        if self.links.is_empty() {
+            return;
+        }
+
+        let n = self.links.len();
+        self.link = Some(match (self.link, forward) {
+            (None, true)     => 0,
+            (None, false)    => n - 1,
+            (Some(i), true)  => (i + 1) % n,
+            (Some(i), false) => (i + n - 1) % n,
+        });
+
+        let line = self.links[self.link.unwrap()].line;
+        if line < self.page_offset || line >= self.page_offset + height {
+            self.page_offset = line.saturating_sub(height / 2);
+        }
+    }
+
+    fn scroll(&mut self, delta: isize, height: usize) {
+        let max = self.lines.len().saturating_sub(height);
+        let offset = self.page_offset as isize + delta;
+        self.page_offset = cmp::min(cmp::max(offset, 0) as usize, max);
+    }
+
+    fn select(&mut self, delta: isize, height: usize) {
+        if self.results.is_empty() {
+            return;
+        }
+
+        let height = cmp::max(height, 1);
+        let selected = self.selected as isize + delta;
+        self.selected = cmp::min(cmp::max(selected, 0) as usize, self.results.len() - 1);
+
+        if self.selected < self.list_offset {
+            self.list_offset = self.selected;
+        } else if self.selected >= self.list_offset + height {
+            self.list_offset = self.selected + 1 - height;
+        }
+    }
+
+    fn draw<W: Write>(&self, out: &mut W, width: usize, height: usize) -> io::Result<()> {
+        let list_width = list_width(width);
+        let body_height = height.saturating_sub(3);
+
+        write!(out, "{}{}", clear::All, cursor::Goto(1, 1))?;
+        write!(out, "{}Search:{} {}", style::Bold, style::Reset, self.query)?;
+
+        for row in 0..body_height {
+            let y = row as u16 + 3;
+
+            if let Some(&(ref name, _)) = self.results.get(self.list_offset + row) {
+                let text = truncate(name, list_width - 1);
+                write!(out, "{}", cursor::Goto(1, y))?;
+                if self.list_offset + row == self.selected {
+                    write!(out, "{}{}{}", style::Invert, text, style::Reset)?;
+                } else {
+                    write!(out, "{}", text)?;
+                }
+            }
+
+            write!(out, "{}|", cursor::Goto(list_width as u16, y))?;
+
+            let line_no = self.page_offset + row;
+            if let Some(line) = self.lines.get(line_no) {
+                write!(out, "{}", cursor::Goto(list_width as u16 + 2, y))?;
+                self.draw_line(out, line_no, line, page_width(width))?;
+            }
+        }
+
+        let help = match self.focus {
+            Focus::Search => "type to search, Up/Down select, Enter open, Esc quit",
+            Focus::Page   => "n/N link, Enter follow, Backspace back, Tab search, q quit",
+        };
+        write!(out, "{}{}{}", cursor::Goto(1, height as u16), style::Invert,
+               truncate(&format!("{}  {}", help, self.status), width))?;
+        write!(out, "{}", style::Reset)?;
+
+        match self.focus {
+            Focus::Search => write!(out, "{}{}", cursor::Goto(9 + self.query.chars().count() as u16, 1),
+                                    cursor::Show)?,
+            Focus::Page   => write!(out, "{}", cursor::Hide)?,
+        }
+
+        out.flush()
+    }
+
+    /// Draw a page line, highlighting the selected link on it.
+    fn draw_line<W: Write>(&self, out: &mut W, line_no: usize, line: &str, width: usize) -> io::Result<()> {
+        let line = truncate(line, width);
+
+        match self.link.map(|i| &self.links[i]) {
+            Some(link) if link.line == line_no && link.end <= line.len() => {
+                write!(out, "{}{}{}{}{}", &line[..link.start], style::Underline,
+                       &line[link.start..link.end], style::Reset, &line[link.end..])
+            },
+            _ => write!(out, "{}", line),
+        }
+    }
+
+    fn run(&mut self) -> errors::Result<()> {
+        let stdin = io::stdin();
+        let mut out = AlternateScreen::from(io::stdout().into_raw_mode()?);
+
+        let (w, h) = termion::terminal_size()?;
+        self.draw(&mut out, w as usize, h as usize)?;
+
+        for key in stdin.keys() {
+            let (w, h) = termion::terminal_size()?;
+            let (width, height) = (w as usize, h as usize);
+            let body_height = height.saturating_sub(3);
+            let page_width = page_width(width);
+
+            match (self.focus, key?) {
+                (_, Key::Ctrl('c')) | (Focus::Search, Key::Esc) | (Focus::Page, Key::Char('q')) => break,
+
+                (Focus::Search, Key::Char('\n')) => {
+                    if let Some((name, url)) = self.results.get(self.selected).cloned() {
+                        self.open(&name, Some(&url), page_width);
+                    }
+                },
+                (Focus::Search, Key::Char('\t')) => if self.page.is_some() { self.focus = Focus::Page },
+                (Focus::Search, Key::Char(c)) => { self.query.push(c); self.update_results(); },
+                (Focus::Search, Key::Backspace) => { self.query.pop(); self.update_results(); },
+                (Focus::Search, Key::Up) => self.select(-1, body_height),
+                (Focus::Search, Key::Down) => self.select(1, body_height),
+                (Focus::Search, Key::PageUp) => self.select(-(body_height as isize), body_height),
+                (Focus::Search, Key::PageDown) => self.select(body_height as isize, body_height),
+
+                (Focus::Page, Key::Char('\t')) | (Focus::Page, Key::Esc) => self.focus = Focus::Search,
+                (Focus::Page, Key::Char('n')) => self.cycle_link(true, body_height),
+                (Focus::Page, Key::Char('N')) => self.cycle_link(false, body_height),
+                (Focus::Page, Key::Char('\n')) => {
+                    if let Some(name) = self.link.map(|i| self.links[i].name.clone()) {
+                        self.open(&name, None, page_width);
+                    }
+                },
+                (Focus::Page, Key::Backspace) | (Focus::Page, Key::Char('b')) => self.back(page_width),
+                (Focus::Page, Key::Up) | (Focus::Page, Key::Char('k')) => self.scroll(-1, body_height),
+                (Focus::Page, Key::Down) | (Focus::Page, Key::Char('j')) => self.scroll(1, body_height),
+                (Focus::Page, Key::PageUp) => self.scroll(-(body_height as isize), body_height),
+                (Focus::Page, Key::PageDown) | (Focus::Page, Key::Char(' ')) => {
+                    self.scroll(body_height as isize, body_height)
+                },
+
+                _ => {},
+            }
+
+            self.draw(&mut out, width, height)?;
+        }
+
+        write!(out, "{}", cursor::Show)?;
+        Ok(())
+    }
+}
+
+
+/// Columns of the list of results on a screen `width` columns wide.
+fn list_width(width: usize) -> usize {
+    cmp::max(width / 3, 20)
+}
+
+/// Columns left for the page next to the list and its border, at least one
+/// however narrow the screen.
+fn page_width(width: usize) -> usize {
+    cmp::max(width.saturating_sub(list_width(width) + 2), 1)
+}
+
+/// Cut `s` to at most `width` characters.
+fn truncate(s: &str, width: usize) -> &str {
+    match s.char_indices().nth(width) {
+        Some((pos, _)) => &s[..pos],
+        None           => s,
+    }
+}
+
+
+/// Browse the index interactively.
+pub fn browse(cppman: &Cppman) -> errors::Result<()> {
+    Browser::new(cppman).run()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{page_width, truncate, Browser, LINK};
+    use ::cppman::Cppman;
+    use ::environ::{Environ, Paths};
+
+    fn with_browser<F: FnOnce(&mut Browser)>(f: F) {
+        let env = Environ::from_paths(Paths::under(env::temp_dir().join("cppman-rs-test-tui"))).unwrap();
+        let cm = Cppman::new_default(&env);
+        f(&mut Browser::new(&cm));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("vector::begin", 6), "vector");
+        assert_eq!(truncate("vector", 10), "vector");
+        assert_eq!(truncate("\u{2264}\u{2265}x", 2), "\u{2264}\u{2265}");
+        assert_eq!(truncate("x", 0), "");
+    }
+
+    #[test]
+    fn test_links() {
+        let names = |line: &str| LINK.captures_iter(line).map(|c| c[1].to_owned()).collect::<Vec<_>>();
+        assert_eq!(names("See vector::insert(3), vector::erase(3cpp)."), vec!["vector::insert", "vector::erase"]);
+        assert_eq!(names("operator<<(3cpp)"), vec!["operator<<"]);
+        assert!(names("f(3c++) g(4)").is_empty());
+    }
+
+    #[test]
+    fn test_page_width() {
+        assert_eq!(page_width(90), 58);
+        assert_eq!(page_width(22), 1);
+        assert_eq!(page_width(10), 1);
+        assert_eq!(page_width(0), 1);
+    }
+
+    #[test]
+    fn test_select() {
+        with_browser(|browser| {
+            browser.select(1, 3);
+            assert_eq!(browser.selected, 0);
+
+            browser.results = (0..10).map(|i| (i.to_string(), String::new())).collect();
+            browser.select(-1, 3);
+            assert_eq!((browser.selected, browser.list_offset), (0, 0));
+            browser.select(4, 3);
+            assert_eq!((browser.selected, browser.list_offset), (4, 2));
+            browser.select(100, 3);
+            assert_eq!((browser.selected, browser.list_offset), (9, 7));
+            browser.select(-8, 3);
+            assert_eq!((browser.selected, browser.list_offset), (1, 1));
+            browser.select(1, 0);
+            assert_eq!((browser.selected, browser.list_offset), (2, 2));
+        });
+    }
+
+    #[test]
+    fn test_scroll() {
+        with_browser(|browser| {
+            browser.scroll(5, 10);
+            assert_eq!(browser.page_offset, 0);
+
+            browser.lines = (0..25).map(|i| i.to_string()).collect();
+            browser.scroll(-3, 10);
+            assert_eq!(browser.page_offset, 0);
+            browser.scroll(10, 10);
+            assert_eq!(browser.page_offset, 10);
+            browser.scroll(100, 10);
+            assert_eq!(browser.page_offset, 15);
+            browser.scroll(100, 40);
+            assert_eq!(browser.page_offset, 0);
+        });
+    }
+}
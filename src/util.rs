@@ -1,100 +1,48 @@
 use std::env;
 use std::error;
 use std::fs::{self, File};
-use std::io::{self, BufReader, BufRead, Write};
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
 
 #[cfg(not(target_os = "windows"))]
 use std::os::unix;
 #[cfg(target_os = "windows")]
 use std::os::windows;
 
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 use select::document::Document;
 use term_size;
 
-use ::environ::Environ;
+use ::config::{Charset, ManSection, Source};
 use ::errors;
+use ::formatter::html2groff;
 
 
-/// Add ~/.local/share/man to $HOME/.manpath
-pub fn update_mandb_path(env: &Environ) -> errors::Result<()> {
-    let home = env::home_dir().unwrap();
-    let manpath_file = home.join(".manpath");
-    let manpath = PathBuf::from(".local/share/man");
-
-    let mut lines: Vec<String> = Vec::new();
-    match File::open(&manpath_file) {
-        Ok(f)  => {
-            lines.extend(BufReader::new(f).lines().filter_map(Result::ok));
-        },
-        Err(_) => {
-            if !env.config.update_man_path() {
-                return Ok(());
-            }
-        },
-    }
-
-    let has_path = lines.iter().any(|l| manpath.to_str().map(|p| l.contains(p)).unwrap_or(false));
-
-    let mut f = File::create(&manpath_file)?;
-    if env.config.update_man_path() {
-        if !has_path {
-            lines.push(format!("MANDATORY_MANPATH\t{}\n", home.join(manpath).display()));
-        }
-    } else {
-        lines = lines
-            .into_iter()
-            .filter(|l| manpath.to_str().map(|p| l.contains(p)).unwrap_or(false))
-            .collect();
-    }
-
-    for line in lines {
-        write!(f, "{}\n", line)?;
-    }
-
-    Ok(())
-}
-
-pub fn update_man3_link(env: &Environ) -> errors::Result<()> {
-    let man3_path = env.man_dir.join("man3");
-
-    if let Ok(metadata) = fs::symlink_metadata(&man3_path) {
-        if metadata.file_type().is_symlink() {
-            let link_to = fs::read_link(&man3_path)?;
-            if link_to == Path::new(&env.config.source().to_string()) {
-                return Ok(());
-            } else {
-                fs::remove_file(&man3_path)?;
-            }
-        } else {
-            panic!("Can't create link since `{}' already exists", man3_path.display());
-        }
-    }
-
-    fs::create_dir_all(env.man_dir.join(env.config.source().to_string()))?;
-
-    create_file_symlink(env.config.source().to_string(), &man3_path)
-}
-
 /// Get terminal width
 pub fn get_width() -> errors::Result<usize> {
     term_size::dimensions_stdout().map(|(w, _)| w).ok_or(errors::ErrorKind::StdoutNoTermWidth.into())
 }
 
 /// Read groff-formatted text and output man pages.
-fn groff2man(data: &[u8]) -> errors::Result<String> {
-    let width = get_width()?;
+pub fn groff2man(data: &[u8], width: usize) -> errors::Result<String> {
+    groff2man_charset(data, width, Charset::Ascii)
+}
 
+/// Read groff-formatted text and output man pages in `charset`. With ASCII
+/// groff approximates the characters it can't output, e.g. `<=` for `≤`.
+pub fn groff2man_charset(data: &[u8], width: usize, charset: Charset) -> errors::Result<String> {
     let mut handle = Command::new("groff")
                              .arg("-t")
-                             .arg("-Tascii")
+                             .arg(format!("-T{}", groff_device(charset)))
                              .args(&["-m", "man"])
                              .arg(format!("-rLL={}n", width))
                              .arg(format!("-rLT={}n", width))
-                             .spawn()
-                             .ok()
-                             .expect("Failed to execute");
+                             .stdin(Stdio::piped())
+                             .stdout(Stdio::piped())
+                             .spawn()?;
 
     {
         let stdin = handle.stdin.as_mut().expect("Couldn't get mutable Pipestream");
@@ -108,10 +56,37 @@ fn groff2man(data: &[u8]) -> errors::Result<String> {
     Ok(man_text)
 }
 
-/// Convert HTML text from cplusplus.com to man pages.
-fn html2man(data: &[u8], formatter: fn(&[u8]) -> String) -> errors::Result<String> {
-    let groff_text = formatter(data);
-    let man_text = groff2man(groff_text.as_bytes())?;
+/// Output device of groff for `charset`.
+pub fn groff_device(charset: Charset) -> &'static str {
+    let utf8 = match charset {
+        Charset::Auto  => locale_is_utf8(),
+        Charset::Ascii => false,
+        Charset::Utf8  => true,
+    };
+
+    if utf8 { "utf8" } else { "ascii" }
+}
+
+/// Whether the locale from the environment uses UTF-8, the first of
+/// `LC_ALL`, `LC_CTYPE` and `LANG` that is set deciding.
+fn locale_is_utf8() -> bool {
+    for var in &["LC_ALL", "LC_CTYPE", "LANG"] {
+        match env::var(var) {
+            Ok(ref value) if !value.is_empty() => {
+                let value = value.to_lowercase();
+                return value.contains("utf-8") || value.contains("utf8");
+            },
+            _ => {},
+        }
+    }
+
+    false
+}
+
+/// Convert HTML text from the given source to man pages.
+pub fn html2man(source: Source, data: &str, name: &str, width: usize) -> errors::Result<String> {
+    let groff_text = html2groff(source, data, &[name.to_owned()], ManSection::default())?;
+    let man_text = groff2man(groff_text.as_bytes(), width)?;
     Ok(man_text)
 }
 
@@ -121,12 +96,53 @@ pub fn fixup_html(data: &str) -> errors::Result<String> {
     Ok(node.html())
 }
 
+/// Write `contents` to `path` unless the file already holds them, creating
+/// the parent directories.
+pub fn write_if_changed<P: AsRef<Path>>(path: P, contents: &str) -> errors::Result<()> {
+    let path = path.as_ref();
+    let mut current = String::new();
+    if let Ok(mut file) = File::open(path) {
+        if file.read_to_string(&mut current).is_ok() && current == contents {
+            return Ok(());
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    File::create(path)?.write_all(contents.as_bytes()).map_err(Into::into)
+}
+
+/// Read a gzip-compressed file into a string.
+pub fn read_gz_file<P: AsRef<Path>>(path: P) -> errors::Result<String> {
+    let file = File::open(path)?;
+    let mut dec = GzDecoder::new(file)?;
+    let mut text = String::new();
+    dec.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Write a string to a gzip-compressed file, creating parent directories.
+pub fn write_gz_file<P: AsRef<Path>>(path: P, text: &str) -> errors::Result<()> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let file = File::create(path)?;
+    let mut enc = GzEncoder::new(file, Compression::Default);
+    enc.write_all(text.as_bytes())?;
+    enc.finish()?;
+    Ok(())
+}
+
 #[cfg(not(target_os = "windows"))]
-fn create_file_symlink<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> errors::Result<()> {
+pub fn create_file_symlink<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> errors::Result<()> {
     unix::fs::symlink(src, dst).map_err(Into::into)
 }
 
 #[cfg(target_os = "windows")]
-fn create_file_symlink<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> errors::Result<()> {
+pub fn create_file_symlink<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> errors::Result<()> {
     windows::fs::symlink_file(src, dst).map_err(Into::into)
 }
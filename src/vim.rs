@@ -0,0 +1,127 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use ::environ::{Environ, xdg_data_home};
+use ::errors;
+use ::util::write_if_changed;
+
+
+/// Plugin files, relative to a Vim runtime directory.
+pub static PLUGIN_FILES: &'static [(&'static str, &'static str)] = &[
+    ("plugin/cppman.vim", include_str!("../data/vim/plugin/cppman.vim")),
+    ("autoload/cppman.vim", include_str!("../data/vim/autoload/cppman.vim")),
+    ("ftplugin/cppman.vim", include_str!("../data/vim/ftplugin/cppman.vim")),
+    ("syntax/cppman.vim", include_str!("../data/vim/syntax/cppman.vim")),
+];
+
+
+#[derive(Copy, Clone)] pub enum Editor { Vim, Neovim }
+
+
+impl Editor {
+    pub fn try_from(s: &str) -> errors::Result<Editor> {
+        match s {
+            "vim"  => Ok(Editor::Vim),
+            "nvim" => Ok(Editor::Neovim),
+            _      => Err(errors::ErrorKind::Usage(format!("unknown editor '{}'", s)).into()),
+        }
+    }
+}
+
+
+/// Package directory the plugin is installed into, loaded automatically by
+/// Vim 8 and Neovim.
+pub fn package_dir(env: &Environ, editor: Editor) -> PathBuf {
+    let pack = match editor {
+        Editor::Vim    => env.home.join(".vim/pack"),
+        Editor::Neovim => xdg_data_home(&env.home).join("nvim/site/pack"),
+    };
+
+    pack.join("cppman-rs/start/cppman-rs")
+}
+
+/// Write the plugin under `dir`, returning the written files.
+pub fn install<P: AsRef<Path>>(dir: P) -> errors::Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+
+    for &(name, contents) in PLUGIN_FILES {
+        let path = dir.as_ref().join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        File::create(&path)?.write_all(contents.as_bytes())?;
+        written.push(path);
+    }
+
+    Ok(written)
+}
+
+/// Script loading the plugin written under `dir`, sourced by the pager when
+/// the plugin isn't installed. The plugin files are kept as they are, as
+/// their guards would end a script holding them all.
+pub fn standalone_config(dir: &Path) -> String {
+    // Commas separate runtime directories, quotes end Vim strings
+    let dir = dir.to_string_lossy().replace(',', "\\,").replace('\'', "''");
+
+    format!("\" cppman-rs: load the plugin for the pager\n\
+             \"\n\
+             \" Generated by cppman-rs.\n\
+             \n\
+             let &runtimepath = '{}' . ',' . &runtimepath\n\
+             runtime plugin/cppman.vim\n\
+             filetype plugin on\n\
+             syntax on\n", dir)
+}
+
+/// Write the plugin next to the pager config at `path`, in `vim`, and the
+/// config loading it, rewriting the files that differ from ours.
+pub fn ensure_pager_config(path: &Path) -> errors::Result<()> {
+    let dir = path.with_file_name("vim");
+    for &(name, contents) in PLUGIN_FILES {
+        write_if_changed(dir.join(name), contents)?;
+    }
+
+    write_if_changed(path, &standalone_config(&dir))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::Path;
+
+    use super::{ensure_pager_config, standalone_config, PLUGIN_FILES};
+
+    fn read(path: &Path) -> String {
+        let mut contents = String::new();
+        File::open(path).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn test_standalone_config() {
+        let config = standalone_config(Path::new("/data/it's,here"));
+        assert!(config.contains("let &runtimepath = '/data/it''s\\,here' . ',' . &runtimepath\n"));
+    }
+
+    #[test]
+    fn test_ensure_pager_config() {
+        let root = env::temp_dir().join("cppman-rs-test-pager-config");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        // A config left by an older version is replaced
+        let path = root.join("cppman-rs.vim");
+        File::create(&path).unwrap().write_all(b"\" old\n").unwrap();
+        ensure_pager_config(&path).unwrap();
+
+        assert_eq!(read(&path), standalone_config(&root.join("vim")));
+        for &(name, contents) in PLUGIN_FILES {
+            assert_eq!(read(&root.join("vim").join(name)), contents);
+        }
+    }
+}